@@ -1,21 +1,53 @@
-use anyhow::{Error, Result};
+use crate::amount::{Amount, Sum};
+use crate::error::EngineError;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
-const LOCKED_ERROR: &str = "Account is locked";
-
 /// An account holds funds and is represented by a
 /// unique ID. Funds can be available, which means
 /// the user has immediate access to these funds.
 /// Funds can also be held, which means that they
-/// are under some sort of dispute. Accounts can
-/// be locked by a chargeback, meaning no new
-/// transactions will succeed.
-#[derive(Default)]
+/// are under some sort of dispute. Funds that are
+/// authorized are set aside for a pending capture,
+/// separately from disputes. Accounts can be
+/// locked by a chargeback or frozen by an admin,
+/// meaning no new transactions will succeed, and
+/// closed for good once they are empty.
+#[derive(Clone, Default)]
 pub struct Account {
     client_id: u16,
-    pub available: f32,
-    pub held: f32,
-    pub locked: bool,
+    pub available: Amount,
+    pub held: Amount,
+    pub authorized: Amount,
+    pub status: AccountStatus,
+}
+
+/// The AccountStatus of an account. Accounts start Active, and move
+/// between states as follows:
+/// ```text
+/// Active -> Locked (by a chargeback)
+///        -> Frozen (by an admin)
+///        -> Closed (by an admin, once empty)
+/// Locked -> Active (unlocked by an admin)
+/// Frozen -> Active (unlocked by an admin)
+/// ```
+/// Locked and Frozen accounts can also be frozen or closed. Closed is
+/// terminal, the account can't be used again.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    Locked,
+    Frozen,
+    Closed,
+}
+
+impl AccountStatus {
+    /// Returns true if the account can be used.
+    pub fn is_active(&self) -> bool {
+        *self == Self::Active
+    }
 }
 
 impl Account {
@@ -27,27 +59,44 @@ impl Account {
         }
     }
 
-    /// Attempt to deposit funds into the available funds.
-    pub fn deposit(&mut self, amount: f32) {
-        self.available += amount;
+    /// Returns the client ID of the account.
+    pub fn client(&self) -> u16 {
+        self.client_id
+    }
+
+    /// Returns true if the account is locked, frozen or closed.
+    pub fn is_locked(&self) -> bool {
+        !self.status.is_active()
+    }
+
+    /// Attempt to deposit funds into the available funds. Locked
+    /// accounts can still take deposits, see `Policy::locked_deposits`.
+    ///
+    /// Returns an Err if the available funds or the total would
+    /// overflow, or the account is closed.
+    pub fn deposit(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_open()?;
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.check_total(available, self.held, self.authorized)?;
+        self.available = available;
+        Ok(())
     }
 
     /// Attempts to withdraw funds from the available funds.
     ///
     /// Returns an Err if there are not enough available
     /// funds or the account is locked.
-    pub fn withdraw(&mut self, amount: f32) -> Result<()> {
-        if self.locked {
-            return Err(Error::msg(LOCKED_ERROR));
-        }
-        if self.available < amount {
-            return Err(Error::msg(format!(
-                "Insufficient funds: has {} wants {}",
-                self.available, amount
-            )));
-        }
+    pub fn withdraw(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_unlocked()?;
+        self.check_available(amount)?;
 
-        self.available -= amount;
+        self.available = self
+            .available
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
         Ok(())
     }
 
@@ -56,19 +105,19 @@ impl Account {
     ///
     /// Returns an Err if there are not enough available
     /// funds or the account is locked.
-    pub fn dispute(&mut self, amount: f32) -> Result<()> {
-        if self.locked {
-            return Err(Error::msg(LOCKED_ERROR));
-        }
-        if self.available < amount {
-            return Err(Error::msg(format!(
-                "Insufficient funds: has {} wants {}",
-                self.available, amount
-            )));
-        }
+    pub fn dispute(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_unlocked()?;
+        self.check_available(amount)?;
 
-        self.available -= amount;
-        self.held += amount;
+        let held = self
+            .held
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.available = self
+            .available
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.held = held;
         Ok(())
     }
 
@@ -76,20 +125,20 @@ impl Account {
     /// funds from hold -> available.
     ///
     /// Returns an Err if there are not enough held
-    /// funds or the account is locked.
-    pub fn resolve(&mut self, amount: f32) -> Result<()> {
-        if self.locked {
-            return Err(Error::msg(LOCKED_ERROR));
-        }
-        if self.held < amount {
-            return Err(Error::msg(format!(
-                "Insufficient funds: has {} wants {}",
-                self.held, amount
-            )));
-        }
+    /// funds or the account is frozen or closed.
+    pub fn resolve(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_settleable()?;
+        self.check_held(amount)?;
 
-        self.available += amount;
-        self.held -= amount;
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.held = self
+            .held
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.available = available;
         Ok(())
     }
 
@@ -97,43 +146,367 @@ impl Account {
     /// from the account.
     ///
     /// Returns an Err if there are not enough held
+    /// funds or the account is frozen or closed.
+    pub fn chargeback(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_settleable()?;
+        self.check_held(amount)?;
+
+        self.held = self
+            .held
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.status = AccountStatus::Locked;
+        Ok(())
+    }
+
+    /// Attempts to mark a withdrawal as disputed, holding the
+    /// withdrawn funds as a provisional credit.
+    ///
+    /// Returns an Err if the held funds or the total would overflow,
+    /// or the account is locked.
+    pub fn dispute_withdrawal(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_unlocked()?;
+
+        let held = self
+            .held
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.check_total(self.available, held, self.authorized)?;
+        self.held = held;
+        Ok(())
+    }
+
+    /// Attempts to resolve a disputed withdrawal, letting the
+    /// withdrawal stand and removing the provisional credit.
+    ///
+    /// Returns an Err if there are not enough held
+    /// funds or the account is frozen or closed.
+    pub fn resolve_withdrawal(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_settleable()?;
+        self.check_held(amount)?;
+
+        self.held = self
+            .held
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        Ok(())
+    }
+
+    /// Attempts to chargeback a disputed withdrawal, reversing it
+    /// by moving the provisional credit from hold -> available.
+    /// This locks the account like any other chargeback.
+    ///
+    /// Returns an Err if there are not enough held
+    /// funds or the account is frozen or closed.
+    pub fn chargeback_withdrawal(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_settleable()?;
+        self.check_held(amount)?;
+
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.held = self
+            .held
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.available = available;
+        self.status = AccountStatus::Locked;
+        Ok(())
+    }
+
+    /// Attempts to authorize funds for a later capture,
+    /// moving the funds from available -> authorized.
+    ///
+    /// Returns an Err if there are not enough available
+    /// funds or the account is locked.
+    pub fn authorize(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_unlocked()?;
+        self.check_available(amount)?;
+
+        let authorized = self
+            .authorized
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.available = self
+            .available
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.authorized = authorized;
+        Ok(())
+    }
+
+    /// Attempts to capture authorized funds, removing them
+    /// from the account like a withdrawal.
+    ///
+    /// Returns an Err if there are not enough authorized
     /// funds or the account is locked.
-    pub fn chargeback(&mut self, amount: f32) -> Result<()> {
-        if self.locked {
-            return Err(Error::msg(LOCKED_ERROR));
+    pub fn capture(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_unlocked()?;
+        self.check_authorized(amount)?;
+
+        self.authorized = self
+            .authorized
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        Ok(())
+    }
+
+    /// Attempts to release authorized funds, moving the
+    /// funds from authorized -> available. This is allowed
+    /// on a locked account, as the funds were never taken.
+    ///
+    /// Returns an Err if there are not enough authorized
+    /// funds.
+    pub fn release(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.check_authorized(amount)?;
+
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.authorized = self
+            .authorized
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.available = available;
+        Ok(())
+    }
+
+    /// Charges a fee, taking it out of the available funds. Fees are
+    /// charged even on locked accounts and may leave the available
+    /// funds negative, as a chargeback fee on an emptied account would.
+    ///
+    /// Returns an Err if the available funds would overflow.
+    pub fn charge_fee(&mut self, amount: Amount) -> Result<(), EngineError> {
+        self.available = self
+            .available
+            .checked_sub(amount)
+            .ok_or_else(|| self.overflow())?;
+        Ok(())
+    }
+
+    /// Reverses a fee, returning it to the available funds.
+    ///
+    /// Returns an Err if the available funds or the total would
+    /// overflow.
+    pub fn reverse_fee(&mut self, amount: Amount) -> Result<(), EngineError> {
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.check_total(available, self.held, self.authorized)?;
+        self.available = available;
+        Ok(())
+    }
+
+    /// Unlocks an account that was locked by a chargeback or frozen,
+    /// so it can be used again.
+    ///
+    /// Returns an Err if the account is not locked or is closed.
+    pub fn unlock(&mut self) -> Result<(), EngineError> {
+        self.check_open()?;
+        if self.status.is_active() {
+            return Err(EngineError::AccountNotLocked {
+                client: self.client_id,
+            });
         }
+        self.status = AccountStatus::Active;
+        Ok(())
+    }
+
+    /// Freezes the account, so no new transactions succeed until it is
+    /// unlocked, like a chargeback does.
+    ///
+    /// Returns an Err if the account is closed.
+    pub fn freeze(&mut self) -> Result<(), EngineError> {
+        self.check_open()?;
+        self.status = AccountStatus::Frozen;
+        Ok(())
+    }
+
+    /// Closes the account for good. Only accounts without any available,
+    /// held or authorized funds can be closed, so nothing is stranded.
+    ///
+    /// Returns an Err if the account still has funds or is already
+    /// closed.
+    pub fn close(&mut self) -> Result<(), EngineError> {
+        self.check_open()?;
+        let empty = [self.available, self.held, self.authorized]
+            .iter()
+            .all(Amount::is_zero);
+        if !empty {
+            return Err(EngineError::AccountNotEmpty {
+                client: self.client_id,
+                total: self.get_total()?,
+            });
+        }
+        self.status = AccountStatus::Closed;
+        Ok(())
+    }
+
+    /// Calculates the total balance of the account, which
+    /// includes held and authorized funds. Every change to
+    /// the balances keeps the total in range, so this only
+    /// fails for balances that were set directly.
+    ///
+    /// Returns an Err if the total does not fit in an Amount.
+    pub fn get_total(&self) -> Result<Amount, EngineError> {
+        self.available
+            .checked_add(self.held)
+            .and_then(|total| total.checked_add(self.authorized))
+            .ok_or_else(|| self.overflow())
+    }
+
+    /// Takes a snapshot of the account's balances.
+    ///
+    /// Returns an Err if the total does not fit in an Amount.
+    pub fn snapshot(&self) -> Result<AccountSnapshot, EngineError> {
+        Ok(AccountSnapshot {
+            client: self.client_id,
+            available: self.available,
+            held: self.held,
+            authorized: self.authorized,
+            total: self.get_total()?,
+            locked: self.is_locked(),
+            status: self.status,
+        })
+    }
+
+    /// Returns an Err if the account is locked, frozen or closed.
+    pub(crate) fn check_unlocked(&self) -> Result<(), EngineError> {
+        self.check_open()?;
+        if self.is_locked() {
+            return Err(EngineError::AccountLocked {
+                client: self.client_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns an Err if the account is frozen or closed. An account
+    /// locked by a chargeback can still settle the disputes that were
+    /// open when it was locked, so their held funds aren't stuck.
+    fn check_settleable(&self) -> Result<(), EngineError> {
+        match self.status {
+            AccountStatus::Active | AccountStatus::Locked => Ok(()),
+            _ => self.check_unlocked(),
+        }
+    }
+
+    /// Returns an Err if the account is closed.
+    fn check_open(&self) -> Result<(), EngineError> {
+        if self.status == AccountStatus::Closed {
+            return Err(EngineError::AccountClosed {
+                client: self.client_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns an Err if the total of a set of balances does not fit in
+    /// an Amount, so that changing to them would leave a total that
+    /// can't be reported.
+    fn check_total(
+        &self,
+        available: Amount,
+        held: Amount,
+        authorized: Amount,
+    ) -> Result<(), EngineError> {
+        available
+            .checked_add(held)
+            .and_then(|total| total.checked_add(authorized))
+            .map(|_| ())
+            .ok_or_else(|| self.overflow())
+    }
+
+    /// Returns an Err if there are fewer available funds than `amount`.
+    fn check_available(&self, amount: Amount) -> Result<(), EngineError> {
+        if self.available < amount {
+            return Err(EngineError::InsufficientFunds {
+                client: self.client_id,
+                requested: amount,
+                available: self.available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns an Err if there are fewer held funds than `amount`.
+    fn check_held(&self, amount: Amount) -> Result<(), EngineError> {
         if self.held < amount {
-            return Err(Error::msg(format!(
-                "Insufficient funds: has {} wants {}",
-                self.held, amount
-            )));
+            return Err(EngineError::InsufficientHeldFunds {
+                client: self.client_id,
+                requested: amount,
+                held: self.held,
+            });
         }
+        Ok(())
+    }
 
-        self.locked = true;
-        self.held -= amount;
+    /// Returns an Err if there are fewer authorized funds than `amount`.
+    fn check_authorized(&self, amount: Amount) -> Result<(), EngineError> {
+        if self.authorized < amount {
+            return Err(EngineError::InsufficientAuthorizedFunds {
+                client: self.client_id,
+                requested: amount,
+                authorized: self.authorized,
+            });
+        }
         Ok(())
     }
 
-    /// Calculates the total balance of the account.
-    pub fn get_total(&self) -> f32 {
-        self.available + self.held
+    /// Creates an overflow error for this account.
+    fn overflow(&self) -> EngineError {
+        EngineError::Overflow {
+            client: self.client_id,
+        }
     }
 }
 
+/// An AccountSnapshot is a copy of an account's balances at a
+/// point in time, including the calculated total, that can be
+/// serialized. Amounts serialize as exact decimal strings:
+/// ```text
+/// {"client":2,"available":"2.0000","held":"0.1234","total":"2.1234","locked":false}
+/// ```
+/// Authorized funds are only included when there are any, and the
+/// status when the account is not active:
+/// ```text
+/// {"client":2,"available":"1.0000","held":"0.0000","authorized":"1.0000",
+///  "total":"2.0000","locked":false}
+/// {"client":3,"available":"0.0000","held":"0.0000","total":"0.0000","locked":true,
+///  "status":"frozen"}
+/// ```
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountSnapshot {
+    pub client: u16,
+    pub available: Amount,
+    pub held: Amount,
+    #[serde(skip_serializing_if = "Amount::is_zero")]
+    pub authorized: Amount,
+    pub total: Amount,
+    pub locked: bool,
+    #[serde(skip_serializing_if = "AccountStatus::is_active")]
+    pub status: AccountStatus,
+}
+
 impl Display for Account {
     /// Displays the account is a CSV format.
-    /// ```
+    /// ```text
     /// 2, 2.0000, 0.1234, 2.1234, false
     /// ```
+    /// The total is added up wide enough that displaying never fails,
+    /// even for balances that were set directly.
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}, {:.4}, {:.4}, {:.4}, {}",
+            "{}, {}, {}, {}, {}",
             self.client_id,
             self.available,
             self.held,
-            self.get_total(),
-            self.locked,
+            Sum::of(&[self.available, self.held, self.authorized]),
+            self.is_locked(),
         )
     }
 }
@@ -0,0 +1,180 @@
+use anyhow::{Error, Result};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Number of decimal places an Amount is exact to.
+pub const DECIMALS: usize = 4;
+
+/// The raw value of a single whole unit, eg. 1.0000.
+const SCALE: i64 = 10_000;
+
+/// An Amount is an exact fixed-point quantity of money with
+/// four decimal places, stored as a count of ten-thousandths.
+/// Unlike floating point values, adding and subtracting
+/// Amounts never loses precision, and overflow is reported
+/// through the checked operations rather than wrapping.
+///
+/// Amounts parse from decimal strings with at most four
+/// decimal places, and display with exactly four:
+/// ```text
+/// 1      -> 1.0000
+/// 0.5555 -> 0.5555
+/// 0.002  -> 0.0020
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i64);
+
+impl Amount {
+    /// Creates an Amount from a raw count of ten-thousandths.
+    pub fn from_raw(raw: i64) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw count of ten-thousandths.
+    pub fn raw(self) -> i64 {
+        self.0
+    }
+
+    /// Adds two Amounts, returning None on overflow.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    /// Subtracts an Amount from this one, returning None on overflow.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Returns true if the Amount is below zero.
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    /// Returns true if the Amount is zero.
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl FromStr for Amount {
+    type Err = Error;
+
+    /// Parses a decimal string such as `-12.3456` into an Amount.
+    ///
+    /// Returns an Err if the string is not a plain decimal number,
+    /// has more than four decimal places, or is out of range.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::msg(format!("Invalid amount '{}'", s));
+
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        // Require at least one digit and nothing but digits on either side.
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        if fraction.len() > DECIMALS {
+            return Err(Error::msg(format!(
+                "Amount '{}' has more than {} decimal places",
+                s, DECIMALS
+            )));
+        }
+
+        // Right pad the fraction so "5" becomes 5000 ten-thousandths.
+        let whole: i64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| invalid())?
+        };
+        let fraction: i64 = format!("{:0<width$}", fraction, width = DECIMALS)
+            .parse()
+            .map_err(|_| invalid())?;
+
+        let raw = whole
+            .checked_mul(SCALE)
+            .and_then(|raw| raw.checked_add(fraction))
+            .ok_or_else(|| Error::msg(format!("Amount '{}' is out of range", s)))?;
+
+        Ok(Self(if negative { -raw } else { raw }))
+    }
+}
+
+impl Display for Amount {
+    /// Displays the Amount with exactly four decimal places.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_raw(f, i128::from(self.0))
+    }
+}
+
+/// A Sum is a sum of Amounts that displays exactly like an Amount, even
+/// when it is too large to fit in one.
+pub(crate) struct Sum(i128);
+
+impl Sum {
+    /// Adds up a set of Amounts.
+    pub(crate) fn of(amounts: &[Amount]) -> Self {
+        Self(amounts.iter().map(|amount| i128::from(amount.0)).sum())
+    }
+}
+
+impl Display for Sum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_raw(f, self.0)
+    }
+}
+
+/// Writes a raw count of ten-thousandths with exactly four decimal places.
+fn write_raw(f: &mut Formatter<'_>, raw: i128) -> std::fmt::Result {
+    let sign = if raw < 0 { "-" } else { "" };
+    // unsigned_abs avoids overflowing on the smallest value.
+    let raw = raw.unsigned_abs();
+    write!(
+        f,
+        "{}{}.{:0width$}",
+        sign,
+        raw / SCALE as u128,
+        raw % SCALE as u128,
+        width = DECIMALS
+    )
+}
+
+impl Serialize for Amount {
+    /// Serializes as an exact decimal string.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    /// Deserializes from a decimal string, following the same rules
+    /// as parsing with `FromStr`.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(AmountVisitor)
+    }
+}
+
+struct AmountVisitor;
+
+impl<'de> Visitor<'de> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "a decimal amount with at most {} places", DECIMALS)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Amount::from_str(v).map_err(E::custom)
+    }
+}
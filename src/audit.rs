@@ -0,0 +1,34 @@
+use crate::account::AccountStatus;
+use serde::{Deserialize, Serialize};
+
+/// An AdminAction is an operation on the status of an account that is
+/// made by an admin rather than a client:
+/// ```text
+/// unlock, 1, 7, , , , alice, chargeback reversed by bank
+/// freeze, 1, 8, , , , alice, suspected fraud
+/// close, 1, 9, , , , bob
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    Unlock,
+    Freeze,
+    Close,
+}
+
+/// An AuditEntry records an admin action that was applied, who made
+/// it and why, along with the status the account had before:
+/// ```text
+/// {"tx":8,"client":1,"action":"freeze","actor":"alice","reason":"suspected fraud",
+///  "previous":"active"}
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub tx: u32,
+    pub client: u16,
+    pub action: AdminAction,
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub previous: AccountStatus,
+}
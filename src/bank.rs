@@ -1,10 +1,26 @@
 use crate::account::Account;
-use crate::transaction::{LoggedTransaction, Transaction};
+use crate::amount::Amount;
+use crate::audit::{AdminAction, AuditEntry};
+use crate::decoder::{CsvDecoder, DecodedRow, Decoder, InputFormat, NdjsonDecoder};
+use crate::error::EngineError;
+use crate::fees::{FeeEntry, FeeSchedule};
+use crate::history::{HistoryEvent, Statement};
+use crate::ledger::{Ledger, LedgerAccount};
+use crate::policy::Policy;
+use crate::query::{AsOf, Cutoff};
+use crate::rejects::{Rejection, RejectsWriter, PARSE_ERROR};
+use crate::report::{compare_accounts, Report, SortKey, SortOrder};
+use crate::snapshot::{AccountRecord, Snapshot, TransactionRecord, SNAPSHOT_VERSION};
+use crate::store::{AccountStore, MemoryAccountStore, MemoryTransactionStore, TransactionStore};
+use crate::transaction::{
+    unix_now, DisputeState, Hold, HoldState, LoggedTransaction, OutOfOrder, Transaction,
+};
+use crate::wal::Wal;
 use anyhow::{Context, Error, Result};
-use csv::Trim;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
 /// The bank holds the state of accounts and transactions, and is in charge
@@ -12,28 +28,57 @@ use std::path::Path;
 /// sane data, such as references to past transactions, before modifying
 /// accounts or inserting into its log.
 ///
-/// Only deposits are logged.
+/// Deposits, withdrawals, transfers and authorizations are logged.
+/// Deposits and transfers can be disputed, and withdrawals can too if
+/// the Policy allows it.
 #[derive(Default)]
-pub struct Bank {
-    accounts: HashMap<u16, Account>,
-    transaction_log: HashMap<u32, LoggedTransaction>,
+pub struct Bank<T: TransactionStore = MemoryTransactionStore, A: AccountStore = MemoryAccountStore>
+{
+    /// The accounts, in memory unless the bank is created with another
+    /// store.
+    accounts: A,
+    /// The log of past transactions, in memory unless the bank is
+    /// created with another store.
+    transaction_log: T,
+    policy: Policy,
+    /// Where rejected rows are written besides stderr, if anywhere.
+    rejects: Option<RejectsWriter>,
+    /// Where transactions are written before they are applied, so the
+    /// state can be rebuilt after a crash.
+    wal: Option<Wal>,
+    /// The expiry and tx ID of authorizations that expire. Entries are
+    /// checked against the log before expiring, so ones that were
+    /// captured or released since are skipped.
+    expiring: BTreeSet<(u64, u32)>,
+    /// The admin operations applied, in the order they were applied.
+    audit: Vec<AuditEntry>,
+    /// The schedule transactions are charged fees from, if any.
+    fees: Option<FeeSchedule>,
+    /// The fees charged and reversed, in the order they were.
+    fee_log: Vec<FeeEntry>,
+    /// Every movement of funds, which the account balances must match,
+    /// if one is kept. See `keep_ledger`.
+    ledger: Option<Ledger>,
+    /// The transactions handled for each client, in the order they were,
+    /// if histories are kept. See `keep_history`.
+    history: Option<BTreeMap<u16, Vec<HistoryEvent>>>,
+    /// The latest timestamp seen, which later ones are checked against.
+    latest_timestamp: Option<u64>,
+    /// The transactions flagged as out of order, in the order they were.
+    out_of_order: Vec<OutOfOrder>,
 }
 
-impl Display for Bank {
-    /// Displays the bank in a CSV format.
+impl<T: TransactionStore, A: AccountStore> Display for Bank<T, A> {
+    /// Displays the bank in a CSV format, ordered by client ID.
     ///
-    /// ```
+    /// ```text
     /// client, available, held, total, locked
+    /// 1, 1.5000, 0.0000, 1.5000, false
     /// 2, 2.0000, 0.1234, 2.1234, false
     /// ```
     /// Note that a bank devoid of accounts will only print a header.
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "client, available, held, total, locked")?;
-        // Loop through all accounts and print them.
-        for account in self.accounts.values() {
-            writeln!(f, "{}", account)?;
-        }
-        Ok(())
+        write!(f, "{}", self.report(SortKey::Client, SortOrder::Ascending))
     }
 }
 
@@ -45,200 +90,1253 @@ impl Bank {
         }
     }
 
+    /// Create a new bank with an empty state that applies a policy.
+    pub fn with_policy(policy: Policy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Moves the accounts, logged transactions, fees and histories out into
+    /// a number of banks with the same policy and fee schedule, split by
+    /// client ID.
+    pub(crate) fn split(&mut self, shards: usize) -> Vec<Bank> {
+        let mut banks: Vec<Bank> = (0..shards)
+            .map(|_| Bank {
+                fees: self.fees.clone(),
+                ledger: self.ledger.as_ref().map(|_| Ledger::default()),
+                history: self.history.as_ref().map(|_| BTreeMap::new()),
+                ..Bank::with_policy(self.policy.clone())
+            })
+            .collect();
+
+        for account in self.accounts.iter() {
+            let shard = usize::from(account.client()) % shards;
+            banks[shard].accounts.insert(account.clone());
+        }
+        // The log is in memory, so iterating over it can't fail.
+        for (tx, logged) in self.transaction_log.iter().flatten() {
+            let shard = usize::from(logged.client) % shards;
+            banks[shard].track_expiry(tx, &logged);
+            let _ = banks[shard].transaction_log.insert(tx, logged);
+        }
+        // Fees go along with their client, so they can be reversed.
+        for entry in self.fee_log.drain(..) {
+            banks[usize::from(entry.client) % shards]
+                .fee_log
+                .push(entry);
+        }
+        for (client, events) in self.history.iter_mut().flat_map(std::mem::take) {
+            if let Some(history) = banks[usize::from(client) % shards].history.as_mut() {
+                history.insert(client, events);
+            }
+        }
+        self.accounts.clear();
+        let _ = self.transaction_log.clear();
+        self.expiring.clear();
+        banks
+    }
+
+    /// Takes the audit log out, leaving it empty.
+    pub(crate) fn take_audit(&mut self) -> Vec<AuditEntry> {
+        std::mem::take(&mut self.audit)
+    }
+
+    /// Adds entries to the end of the audit log.
+    pub(crate) fn extend_audit<I: IntoIterator<Item = AuditEntry>>(&mut self, entries: I) {
+        self.audit.extend(entries);
+    }
+
+    /// Iterates over the logged transactions.
+    pub(crate) fn logged_transactions(
+        &self,
+    ) -> impl Iterator<Item = (u32, LoggedTransaction)> + '_ {
+        // The log is in memory, so iterating over it can't fail.
+        self.transaction_log.iter().flatten()
+    }
+
+    /// Removes a client's account and history, returning the account if
+    /// it existed.
+    pub(crate) fn take_client(&mut self, client: u16) -> (Option<Account>, Vec<HistoryEvent>) {
+        let account = self.accounts.get(client).cloned();
+        self.accounts.remove(client);
+        let history = self
+            .history
+            .as_mut()
+            .and_then(|history| history.remove(&client));
+        (account, history.unwrap_or_default())
+    }
+
+    /// Inserts a client's account if it has one, replacing one with the
+    /// same client ID, and adds to the end of their history.
+    pub(crate) fn give_client(
+        &mut self,
+        client: u16,
+        account: Option<Account>,
+        history: Vec<HistoryEvent>,
+    ) {
+        if let Some(account) = account {
+            self.accounts.insert(account);
+        }
+        match self.history.as_mut() {
+            Some(kept) if !history.is_empty() => kept.entry(client).or_default().extend(history),
+            _ => {}
+        }
+    }
+
+    /// Moves the accounts, logged transactions, fees and histories of
+    /// another bank into this one. The banks are expected to hold different clients.
+    pub(crate) fn absorb(&mut self, other: Bank) {
+        for account in other.accounts.iter() {
+            self.accounts.insert(account.clone());
+        }
+        for (tx, logged) in other.transaction_log.iter().flatten() {
+            let _ = self.transaction_log.insert(tx, logged);
+        }
+        self.expiring.extend(other.expiring);
+        self.audit.extend(other.audit);
+        self.fee_log.extend(other.fee_log);
+        if let (Some(ledger), Some(other)) = (self.ledger.as_mut(), other.ledger) {
+            ledger.extend(other);
+        }
+        if let (Some(history), Some(other)) = (self.history.as_mut(), other.history) {
+            for (client, events) in other {
+                history.entry(client).or_default().extend(events);
+            }
+        }
+    }
+}
+
+impl<T: TransactionStore> Bank<T> {
+    /// Create a new bank that applies a policy and logs transactions
+    /// into a store. The store is expected to be empty.
+    pub fn with_store(transaction_log: T, policy: Policy) -> Self {
+        Bank::with_stores(MemoryAccountStore::default(), transaction_log, policy)
+    }
+}
+
+impl<T: TransactionStore, A: AccountStore> Bank<T, A> {
+    /// Create a new bank that applies a policy, keeps accounts in one
+    /// store and logs transactions into another. The stores are
+    /// expected to be empty.
+    pub fn with_stores(accounts: A, transaction_log: T, policy: Policy) -> Self {
+        Self {
+            accounts,
+            transaction_log,
+            policy,
+            rejects: None,
+            wal: None,
+            expiring: BTreeSet::new(),
+            audit: Vec::new(),
+            fees: None,
+            fee_log: Vec::new(),
+            ledger: None,
+            history: None,
+            latest_timestamp: None,
+            out_of_order: Vec::new(),
+        }
+    }
+
+    /// Returns the policy the bank applies.
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Sets a sink that every rejected input row is written to.
+    pub fn set_rejects(&mut self, rejects: RejectsWriter) {
+        self.rejects = Some(rejects);
+    }
+
+    /// Sets a fee schedule that every handled transaction is charged
+    /// from. See `handle_transaction`.
+    pub fn set_fees(&mut self, fees: FeeSchedule) {
+        self.fees = Some(fees);
+    }
+
+    /// Sets a write-ahead log that every transaction changing the state
+    /// is written to. See `handle_transaction`.
+    pub fn set_wal(&mut self, wal: Wal) {
+        self.wal = Some(wal);
+    }
+
+    /// Replays the transactions of a write-ahead log at a path on top
+    /// of the current state, returning the number of entries replayed.
+    /// A log that does not exist has nothing to replay.
+    ///
+    /// Replayed transactions are not written to the log again, and are
+    /// checked for order again, so the latest timestamp and the flags are
+    /// rebuilt without being printed. Entries that fail to parse, such as
+    /// a torn last entry from a crash, are skipped with an error on
+    /// stderr.
+    ///
+    /// Returns an Err if the log can't be read.
+    pub fn replay_wal<P: AsRef<Path>>(&mut self, path: P) -> Result<u64> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(0);
+        }
+        let decoder = NdjsonDecoder::new(File::open(path)?);
+
+        // Take the log out while replaying so entries aren't duplicated.
+        let wal = self.wal.take();
+        let result = self.replay_decoder(&path.display().to_string(), decoder);
+        self.wal = wal;
+        result
+    }
+
+    /// Replays the entries of a write-ahead log from a decoder.
+    ///
+    /// Returns an Err if the log can't be read.
+    fn replay_decoder<D: Decoder>(&mut self, source: &str, mut decoder: D) -> Result<u64> {
+        let mut replayed = 0;
+        while let Some(row) = decoder.next_row() {
+            let row = row?;
+            match row.transaction {
+                Ok(transaction) => {
+                    self.check_order(&transaction);
+                    // Entries replay the same way they were first handled,
+                    // which includes rejections that opened an account, so
+                    // their errors are expected. Holds that ran out were
+                    // expired by entries of their own, so aren't again.
+                    let _ = self.handle(transaction);
+                    replayed += 1;
+                }
+                Err(err) => eprintln!("{}:{}: {}, skipping...", source, row.line, err),
+            }
+        }
+        Ok(replayed)
+    }
+
     /// Returns the number of accounts.
-    #[cfg(test)]
     pub fn num_accounts(&self) -> usize {
         self.accounts.len()
     }
 
+    /// Returns all accounts sorted by a key in an order. Accounts that
+    /// are equal by the key are ordered by client ID.
+    pub fn sorted_accounts(&self, key: SortKey, order: SortOrder) -> Vec<&Account> {
+        let mut accounts: Vec<&Account> = self.accounts.iter().collect();
+        accounts.sort_unstable_by(|a, b| compare_accounts(a, b, key, order));
+        accounts
+    }
+
+    /// Creates a displayable Report of the accounts sorted by a key
+    /// in an order.
+    pub fn report(&self, key: SortKey, order: SortOrder) -> Report<'_> {
+        Report::new(self, key, order)
+    }
+
     /// Attempts to fetch an account by client ID, returning a
     /// reference to the account if it exists.
-    #[cfg(test)]
     pub fn get_account(&self, client: u16) -> Option<&Account> {
-        self.accounts.get(&client)
+        self.accounts.get(client)
     }
 
     /// Attempts to fetch an account by client ID, creating a new one
     /// if one does not exist. Returns a mutable reference to the account.
     fn get_or_create_account(&mut self, client: u16) -> &mut Account {
-        self.accounts
-            .entry(client)
-            .or_insert_with(|| Account::new(client))
+        self.accounts.get_or_create(client)
+    }
+
+    /// Returns the admin operations that were applied, in order.
+    pub fn audit_log(&self) -> &[AuditEntry] {
+        &self.audit
+    }
+
+    /// Returns the fees that were charged and reversed, in order.
+    pub fn fee_log(&self) -> &[FeeEntry] {
+        &self.fee_log
+    }
+
+    /// Returns the transactions flagged as out of order, in the order
+    /// they were flagged.
+    pub fn out_of_order(&self) -> &[OutOfOrder] {
+        &self.out_of_order
+    }
+
+    /// Returns the latest timestamp seen, which disputes without one are
+    /// taken to be as late as.
+    pub fn latest_timestamp(&self) -> Option<u64> {
+        self.latest_timestamp
+    }
+
+    /// Sets the latest timestamp seen, for shards that only see some of
+    /// the transactions.
+    pub(crate) fn set_latest_timestamp(&mut self, latest: Option<u64>) {
+        self.latest_timestamp = latest;
+    }
+
+    /// Checks a transaction's timestamp against the latest one seen,
+    /// flagging it if it is earlier. Transactions without a timestamp
+    /// are never out of order. Inputs are checked as they are processed,
+    /// but transactions handled one at a time are not.
+    ///
+    /// Returns the flag if the transaction is out of order.
+    pub fn check_order(&mut self, transaction: &Transaction) -> Option<&OutOfOrder> {
+        let timestamp = transaction.timestamp?;
+        match self.latest_timestamp {
+            Some(latest) if timestamp < latest => {
+                self.out_of_order.push(OutOfOrder {
+                    tx: transaction.tx,
+                    client: transaction.client,
+                    timestamp,
+                    latest,
+                });
+                self.out_of_order.last()
+            }
+            _ => {
+                self.latest_timestamp = Some(timestamp);
+                None
+            }
+        }
+    }
+
+    /// Returns the transactions handled for a client, accepted or not, in
+    /// the order they were handled. Empty unless histories are kept.
+    pub fn history(&self, client: u16) -> &[HistoryEvent] {
+        self.history
+            .as_ref()
+            .and_then(|history| history.get(&client))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Starts keeping the history of every client from the next
+    /// transaction handled. Histories grow with every transaction, so
+    /// they are only kept when asked for, or when a snapshot with
+    /// histories is loaded.
+    pub fn keep_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(BTreeMap::new());
+        }
+    }
+
+    /// Creates a displayable Statement of a client's history.
+    pub fn statement(&self, client: u16) -> Statement<'_> {
+        Statement::new(self.history(client))
+    }
+
+    /// Starts keeping a ledger of every movement of funds, which the
+    /// account balances can be checked against with `verify_ledger`. The
+    /// balances accounts already have are posted as opening balances.
+    /// The ledger grows with every transaction, so it is only kept when
+    /// asked for, or when a snapshot with a ledger is loaded.
+    pub fn keep_ledger(&mut self) {
+        if self.ledger.is_none() {
+            self.ledger = Some(Ledger::default());
+            self.open_ledger();
+        }
+    }
+
+    /// Posts the balances of every account as opening balances, if a
+    /// ledger is kept.
+    fn open_ledger(&mut self) {
+        let Some(ledger) = self.ledger.as_mut() else {
+            return;
+        };
+        for account in self.accounts.iter() {
+            let client = account.client();
+            for (to, amount) in [
+                (LedgerAccount::Available(client), account.available),
+                (LedgerAccount::Held(client), account.held),
+                (LedgerAccount::Authorized(client), account.authorized),
+            ] {
+                ledger.post(None, LedgerAccount::Opening, to, amount);
+            }
+        }
+    }
+
+    /// Returns the ledger of every movement of funds, if one is kept.
+    pub fn ledger(&self) -> Option<&Ledger> {
+        self.ledger.as_ref()
+    }
+
+    /// Checks that the ledger conserves money and that every account
+    /// balance matches it.
+    ///
+    /// Returns an Err describing the first difference found, or if no
+    /// ledger is kept.
+    pub fn verify_ledger(&self) -> Result<()> {
+        match &self.ledger {
+            Some(ledger) => ledger.verify(self.accounts.iter()),
+            None => Err(Error::msg("No ledger is kept")),
+        }
+    }
+
+    /// Records funds moving between ledger accounts for a transaction,
+    /// if a ledger is kept.
+    fn post(&mut self, tx: u32, from: LedgerAccount, to: LedgerAccount, amount: Amount) {
+        if let Some(ledger) = self.ledger.as_mut() {
+            ledger.post(Some(tx), from, to, amount);
+        }
     }
 
     /// Returns the number of logged transactions.
-    #[cfg(test)]
     pub fn num_logs(&self) -> usize {
         self.transaction_log.len()
     }
 
     /// Attempts to fetch a logged transaction by transaction ID, returning
-    /// a reference to the LoggedTransaction if it exists.
-    #[cfg(test)]
-    pub fn get_logged_transaction(&self, tx: u32) -> Option<&LoggedTransaction> {
-        self.transaction_log.get(&tx)
+    /// a copy of the LoggedTransaction if it exists. A transaction that
+    /// can't be read from the store is treated as not existing.
+    pub fn get_logged_transaction(&self, tx: u32) -> Option<LoggedTransaction> {
+        self.transaction_log.get(tx).ok().flatten()
     }
 
-    /// Sets a logged transaction to be disputed or not.
+    /// Saves the accounts and logged transactions to a snapshot file,
+    /// see `snapshot::Snapshot` for the format. The snapshot is written
+    /// next to the path first and then moved over it, so an existing
+    /// snapshot is never left half written. Once it is saved, the
+    /// write-ahead log is truncated as the snapshot covers its entries.
     ///
-    /// Returns an Err if the logged transaction does not exist.
-    fn set_disputed(&mut self, tx: u32, disputed: bool) -> Result<()> {
-        // Get the transaction referenced by this transaction, returning
-        // early if that transaction does not exist.
-        let in_question = self
+    /// Returns an Err if the snapshot can't be written.
+    pub fn save_snapshot<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        self.write_snapshot(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&partial, path)?;
+
+        if let Some(wal) = self.wal.as_mut() {
+            wal.truncate()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the accounts and logged transactions as a snapshot.
+    ///
+    /// Returns an Err if the writer fails.
+    pub fn write_snapshot<W: Write>(&self, writer: W) -> Result<()> {
+        let mut accounts: Vec<&Account> = self.accounts.iter().collect();
+        accounts.sort_unstable_by_key(|account| account.client());
+        // Stores iterate in tx ID order, so no sorting is needed.
+        let transactions = self
             .transaction_log
-            .get_mut(&tx)
-            .context(format!("Invalid transaction reference {}", tx))?;
-        in_question.disputed = disputed;
+            .iter()
+            .map(|entry| entry.map(|(tx, logged)| TransactionRecord::new(tx, &logged)))
+            .collect::<Result<Vec<_>>>()?;
 
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            accounts: accounts.into_iter().map(AccountRecord::from).collect(),
+            transactions,
+            audit: self.audit.clone(),
+            fees: self.fee_log.clone(),
+            ledger: self
+                .ledger
+                .as_ref()
+                .map_or_else(Vec::new, |ledger| ledger.postings().to_vec()),
+            history: self
+                .history
+                .iter()
+                .flat_map(BTreeMap::values)
+                .flatten()
+                .cloned()
+                .collect(),
+            latest_timestamp: self.latest_timestamp,
+            out_of_order: self.out_of_order.clone(),
+        };
+        serde_json::to_writer(writer, &snapshot)?;
         Ok(())
     }
 
+    /// Loads the accounts and logged transactions from a snapshot file
+    /// written by `save_snapshot`, replacing the current state. The
+    /// policy and rejects sink are kept.
+    ///
+    /// Returns an Err if the file can't be read, or is not a snapshot
+    /// of a version this build can read.
+    pub fn load_snapshot<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        self.read_snapshot(BufReader::new(file))
+            .with_context(|| format!("Invalid snapshot {}", path.display()))
+    }
+
+    /// Reads a snapshot, replacing the current accounts and logged
+    /// transactions.
+    ///
+    /// Returns an Err if the reader fails, or the snapshot is malformed
+    /// or of a version newer than `SNAPSHOT_VERSION`.
+    pub fn read_snapshot<R: Read>(&mut self, reader: R) -> Result<()> {
+        let snapshot: Snapshot = serde_json::from_reader(reader)?;
+        if snapshot.version == 0 || snapshot.version > SNAPSHOT_VERSION {
+            return Err(Error::msg(format!(
+                "Unsupported snapshot version {}, expected {} or older",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        self.accounts.clear();
+        for record in snapshot.accounts {
+            // Accounts always keep a total that can be reported.
+            let account = Account::from(record);
+            account.get_total()?;
+            self.accounts.insert(account);
+        }
+        self.transaction_log.clear()?;
+        self.expiring.clear();
+        for record in snapshot.transactions {
+            let tx = record.tx;
+            let logged = LoggedTransaction::from(record);
+            self.track_expiry(tx, &logged);
+            self.transaction_log.insert(tx, logged)?;
+        }
+        self.audit = snapshot.audit;
+        self.fee_log = snapshot.fees;
+        self.latest_timestamp = snapshot.latest_timestamp;
+        self.out_of_order = snapshot.out_of_order;
+        // A snapshot with histories keeps them, so saving it again
+        // doesn't lose them.
+        if !snapshot.history.is_empty() {
+            self.keep_history();
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+            for event in snapshot.history {
+                history.entry(event.client).or_default().push(event);
+            }
+        }
+
+        // A snapshot with a ledger keeps it, so saving it again doesn't
+        // lose the journal. Snapshots from before the ledger, or of banks
+        // that didn't keep one, only have the balances, so they are
+        // brought in as opening balances.
+        if !snapshot.ledger.is_empty() && self.ledger.is_none() {
+            self.ledger = Some(Ledger::default());
+        }
+        if let Some(ledger) = self.ledger.as_mut() {
+            ledger.replace(snapshot.ledger);
+            if ledger.is_empty() {
+                self.open_ledger();
+            }
+        }
+        Ok(())
+    }
+
+    /// Tracks a logged authorization if it is still held and expires.
+    fn track_expiry(&mut self, tx: u32, logged: &LoggedTransaction) {
+        if let Some(hold) = logged.hold {
+            if let (HoldState::Authorized, Some(expires)) = (hold.state, hold.expires) {
+                self.expiring.insert((expires, tx));
+            }
+        }
+    }
+
+    /// Expires every authorization whose expiry is at or before `now`
+    /// in unix seconds, returning their funds to available, and returns
+    /// the number expired. Each expiry is handled as an `expire`
+    /// transaction, so it is written to the write-ahead log if one is
+    /// set.
+    ///
+    /// Returns an Err if an expiry fails to be handled, in which case
+    /// the authorization is left held and expiring.
+    pub fn expire_holds(&mut self, now: u64) -> Result<usize, EngineError> {
+        let mut expired = 0;
+        while let Some(&(expires, tx)) = self.expiring.first() {
+            if expires > now {
+                break;
+            }
+            let logged = self.transaction_log.get(tx).map_err(storage_error(tx))?;
+            let held = Hold {
+                state: HoldState::Authorized,
+                expires: Some(expires),
+            };
+            match logged {
+                Some(logged) if logged.hold == Some(held) => {
+                    self.handle(Transaction::new("expire", logged.client, tx, None))?;
+                    expired += 1;
+                }
+                // Captured or released since, or undone.
+                _ => {}
+            }
+            self.expiring.remove(&(expires, tx));
+        }
+        Ok(expired)
+    }
+
+    /// Expires a logged authorization if its hold expires at or before
+    /// `now` in unix seconds. The expiry is handled as an `expire`
+    /// transaction, like in `expire_holds`.
+    ///
+    /// Returns an Err if the expiry fails to be handled.
+    fn expire_hold_by(&mut self, tx: u32, now: u64) -> Result<(), EngineError> {
+        let logged = match self.transaction_log.get(tx).map_err(storage_error(tx))? {
+            Some(logged) => logged,
+            None => return Ok(()),
+        };
+        if let Some(Hold {
+            state: HoldState::Authorized,
+            expires: Some(expires),
+        }) = logged.hold
+        {
+            if expires <= now {
+                self.handle(Transaction::new("expire", logged.client, tx, None))?;
+                self.expiring.remove(&(expires, tx));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a logged transaction back with its updated dispute state
+    /// and portions.
+    ///
+    /// Returns an Err if the transaction store fails.
+    fn update_dispute(&mut self, tx: u32, logged: LoggedTransaction) -> Result<(), EngineError> {
+        self.transaction_log
+            .insert(tx, logged)
+            .map_err(storage_error(tx))
+    }
+
+    /// Checks that a logged transaction can move from its dispute state
+    /// to another under the policy.
+    ///
+    /// Returns an Err describing why the transition isn't allowed.
+    fn check_transition(
+        &self,
+        tx: u32,
+        logged: &LoggedTransaction,
+        to: DisputeState,
+    ) -> Result<(), EngineError> {
+        use DisputeState::*;
+
+        match (logged.state, to) {
+            (Undisputed, Disputed) | (Disputed, Resolved) | (Disputed, ChargedBack) => Ok(()),
+            // Part of it may still be left to dispute.
+            (Disputed | Resolved, Disputed) if self.disputable(logged) > Amount::default() => {
+                Ok(())
+            }
+            (Resolved, Disputed) => Err(EngineError::AlreadyResolved { tx }),
+            (Disputed, Disputed) => Err(EngineError::AlreadyDisputed { tx }),
+            (ChargedBack, _) => Err(EngineError::AlreadyChargedBack { tx }),
+            _ => Err(EngineError::NotDisputed { tx }),
+        }
+    }
+
+    /// Returns the portion of a logged transaction that can still be
+    /// disputed. The resolved portion only can be if the policy allows
+    /// disputing it again, while the rest never disputed always can.
+    fn disputable(&self, logged: &LoggedTransaction) -> Amount {
+        match self.policy.redispute_resolved {
+            true => logged.undisputed(),
+            false => logged.never_disputed(),
+        }
+    }
+
     /// Attempts to validate the transaction referenced by the supplied
-    /// transaction, returning a reference to it if it is valid.
+    /// transaction, and that it can move to the `next` dispute state,
+    /// returning a copy of it if it is valid.
     ///
     /// Returns an Err if the transaction fails to validate.
     fn validate_transaction_reference(
         &self,
         transaction: &Transaction,
-        disputed: bool,
-    ) -> Result<&LoggedTransaction> {
+        next: DisputeState,
+    ) -> Result<LoggedTransaction, EngineError> {
+        let tx = transaction.tx;
+
         // Get the transaction referenced by this transaction, returning
         // early if that transaction does not exist.
         let in_question = self
             .transaction_log
-            .get(&transaction.tx)
-            .context(format!("Invalid transaction reference {}", transaction.tx))?;
+            .get(tx)
+            .map_err(storage_error(tx))?
+            .ok_or(EngineError::UnknownTransaction { tx })?;
 
-        if !in_question.deposit {
-            return Err(Error::msg(format!(
-                "Transaction {} is not deposit",
-                transaction.tx
-            )));
-        }
+        self.check_disputable(tx, &in_question)?;
 
         if in_question.client != transaction.client {
             // The supplied client does not match the referenced client,
             // it may be erroneous.
-            return Err(Error::msg(format!(
-                "Client value {} did not match reference client {} for transaction {}",
-                transaction.client, in_question.client, transaction.tx
-            )));
+            return Err(EngineError::ClientMismatch {
+                tx,
+                client: transaction.client,
+                expected: in_question.client,
+            });
         }
 
-        if in_question.disputed == disputed {
-            // We're already disputing this transaction
-            return Err(Error::msg(format!(
-                "Transaction {} disputed is not {}",
-                transaction.tx, disputed
-            )));
+        self.check_transition(tx, &in_question, next)?;
+
+        Ok(in_question)
+    }
+
+    /// Checks that a logged transaction is of a kind that can be disputed.
+    /// Deposits and transfers always can, and withdrawals can if the
+    /// policy allows it.
+    ///
+    /// Returns an Err if the transaction can't be disputed.
+    pub(crate) fn check_disputable(
+        &self,
+        tx: u32,
+        logged: &LoggedTransaction,
+    ) -> Result<(), EngineError> {
+        if logged.hold.is_some() {
+            return Err(EngineError::NotDeposit { tx });
+        }
+        let withdrawal = !logged.deposit && logged.destination.is_none();
+        if withdrawal && !self.policy.dispute_withdrawals {
+            return Err(EngineError::NotDeposit { tx });
+        }
+        Ok(())
+    }
+
+    /// Checks that a logged transaction is an authorization.
+    ///
+    /// Returns an Err if the transaction is not an authorization.
+    pub(crate) fn check_authorization(
+        &self,
+        tx: u32,
+        logged: &LoggedTransaction,
+    ) -> Result<(), EngineError> {
+        match logged.hold {
+            Some(_) => Ok(()),
+            None => Err(EngineError::NotAuthorization { tx }),
+        }
+    }
+
+    /// Attempts to validate the authorization referenced by the supplied
+    /// transaction, and that it is still held, returning a copy of it if
+    /// it is valid.
+    ///
+    /// Returns an Err if the authorization fails to validate.
+    fn validate_hold_reference(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<LoggedTransaction, EngineError> {
+        let tx = transaction.tx;
+
+        let in_question = self
+            .transaction_log
+            .get(tx)
+            .map_err(storage_error(tx))?
+            .ok_or(EngineError::UnknownTransaction { tx })?;
+
+        self.check_authorization(tx, &in_question)?;
+
+        if in_question.client != transaction.client {
+            return Err(EngineError::ClientMismatch {
+                tx,
+                client: transaction.client,
+                expected: in_question.client,
+            });
+        }
+
+        if in_question.hold.map(|hold| hold.state) != Some(HoldState::Authorized) {
+            return Err(EngineError::HoldNotActive { tx });
         }
 
         Ok(in_question)
     }
 
+    /// Sets the state of a logged authorization's hold.
+    ///
+    /// Returns an Err if the logged authorization does not exist.
+    fn set_hold_state(&mut self, tx: u32, state: HoldState) -> Result<(), EngineError> {
+        let mut in_question = self
+            .transaction_log
+            .get(tx)
+            .map_err(storage_error(tx))?
+            .ok_or(EngineError::UnknownTransaction { tx })?;
+        let hold = in_question
+            .hold
+            .as_mut()
+            .ok_or(EngineError::NotAuthorization { tx })?;
+        hold.state = state;
+
+        self.transaction_log
+            .insert(tx, in_question)
+            .map_err(storage_error(tx))
+    }
+
     /// Converts a transaction to a LoggedTransaction and inserts it into
     /// the log, keyed by its transaction ID.
-    fn log_transaction(&mut self, transaction: Transaction) -> Result<()> {
+    fn log_transaction(&mut self, transaction: Transaction) -> Result<(), EngineError> {
         // Turn into a LoggedTransaction which strips off the transaction ID.
+        let tx = transaction.tx;
         self.transaction_log
-            .insert(transaction.tx, LoggedTransaction::try_from(transaction)?);
-        Ok(())
+            .insert(tx, LoggedTransaction::try_from(transaction)?)
+            .map_err(storage_error(tx))
     }
 
-    /// Attempts to parse the passed transaction_path as a CSV file and
-    /// deserialize them into Transactions, handling them in order from
-    /// top to bottom.
+    /// Attempts to parse the passed transaction_path as a CSV or NDJSON
+    /// file, guessed from its extension, and deserialize them into
+    /// Transactions, handling them in order from top to bottom. See
+    /// `process_decoder` for how failures are handled.
     ///
     /// This function returns an Err if the file fails to open.
+    pub fn process_transactions<P: AsRef<Path>>(&mut self, transaction_path: P) -> Result<()> {
+        let transaction_path = transaction_path.as_ref();
+
+        // Only require read permissions on the file.
+        let file = File::options().read(true).open(transaction_path)?;
+        let decoder = InputFormat::from_path(transaction_path).decoder(file)?;
+        self.process_decoder(&transaction_path.display().to_string(), decoder)
+    }
+
+    /// Attempts to parse the passed reader as CSV and deserialize it into
+    /// Transactions, handling them in order from top to bottom. See
+    /// `process_decoder` for how failures are handled.
+    ///
+    /// This function returns an Err if the header row can't be read.
+    pub fn process_reader<R: Read>(&mut self, source: &str, reader: R) -> Result<()> {
+        self.process_decoder(source, CsvDecoder::new(reader)?)
+    }
+
+    /// Decodes Transactions from the passed decoder, handling them in
+    /// order from top to bottom. `source` names the input in reports,
+    /// such as a file path or `-` for stdin.
+    ///
+    /// Calling this repeatedly processes each input into the same state,
+    /// so a set of inputs can be processed in sequence.
+    ///
+    /// This function returns an Err if the input can't be read.
     ///
     /// If a line fails to parse as a Transaction or fails to be handled,
-    /// the line is ignored and an error is printed on stderr.
-    pub fn process_transactions<P: AsRef<Path>>(&mut self, transaction_path: P) -> Result<()> {
-        // Read the file as csv, only requiring read permissions on the file.
-        // The CSV is trimmed of any whitespaces and allows a variable number
-        // of fields to allow amounts to be ignored.
-        //
-        // Note: The csv library does not support UTF16.
-        let mut transactions = csv::ReaderBuilder::new()
-            .trim(Trim::All)
-            .flexible(true)
-            .from_reader(File::options().read(true).open(transaction_path)?);
-
-        // `deserialize()` will read the data in chunks (BufReader under the hood)
-        // so that the entire file isn't loaded into memory at once.
-        for transaction_result in transactions.deserialize() {
-            // Type hint for deserialize to know we want Transaction.
-            // This could also be turbofished in like
-            // `deserialize::<Transaction>()` instead.
-            let transaction: Transaction = match transaction_result {
+    /// the line is ignored and an error is printed on stderr. If a rejects
+    /// sink is set the line is also written there, and this function returns
+    /// an Err if writing to the sink fails.
+    pub fn process_decoder<D: Decoder>(&mut self, source: &str, mut decoder: D) -> Result<()> {
+        // Decoders read the data in chunks so that the entire input isn't
+        // loaded into memory at once. The raw row is kept around in case
+        // the transaction gets rejected.
+        while let Some(row) = decoder.next_row() {
+            let DecodedRow {
+                line,
+                transaction,
+                raw,
+            } = row?;
+
+            let transaction = match transaction {
                 Ok(transaction) => transaction,
                 Err(err) => {
                     // Skip entries that fail to parse as transactions.
-                    eprintln!("{:?}, skipping...", err);
+                    self.reject(source, line, &raw.text(), PARSE_ERROR, err)?;
                     continue;
                 }
             };
 
+            // Out of order transactions are still handled, but flagged.
+            if let Some(flag) = self.check_order(&transaction) {
+                eprintln!("{}:{}: {}", source, line, flag);
+            }
+
             // Handle the transaction. Note that this moves `transaction`.
             if let Err(err) = self.handle_transaction(transaction) {
                 // The transaction has failed!
-                eprintln!("{:?}, skipping...", err);
+                self.reject(source, line, &raw.text(), err.code(), err.to_string())?;
             }
         }
 
+        self.flush()
+    }
+
+    /// Processes inputs up to a point in them, and returns a client's
+    /// account as it was at that point, or None if it had none yet. Each
+    /// input is named the way it is in `AsOf::Line` and in reports, and
+    /// is handled the same way `process_decoder` handles it.
+    ///
+    /// Returns an Err if an input can't be read, or the inputs end before
+    /// the point.
+    pub fn balance_as_of<'a, I, D>(
+        &mut self,
+        client: u16,
+        as_of: AsOf,
+        inputs: I,
+    ) -> Result<Option<Account>>
+    where
+        I: IntoIterator<Item = (&'a str, D)>,
+        D: Decoder,
+    {
+        let mut cutoff = Cutoff::new(as_of);
+        for (source, decoder) in inputs {
+            if cutoff.reached() {
+                break;
+            }
+            self.process_decoder(source, cutoff.decoder(source, decoder))?;
+        }
+        cutoff.check()?;
+        Ok(self.accounts.get(client).cloned())
+    }
+
+    /// Flushes the rejects sink, syncs the write-ahead log and persists
+    /// the accounts. This is done at the end of every processed input,
+    /// and should be done after handling transactions one at a time.
+    ///
+    /// Returns an Err if any of them fail.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(rejects) = self.rejects.as_mut() {
+            rejects.flush()?;
+        }
+        if let Some(wal) = self.wal.as_mut() {
+            wal.sync()?;
+        }
+        self.accounts.persist()
+    }
+
+    /// Reports a rejected input row on stderr and to the rejects sink if
+    /// one is set.
+    ///
+    /// Returns an Err if writing to the rejects sink fails.
+    pub(crate) fn reject(
+        &mut self,
+        source: &str,
+        line: u64,
+        record: &str,
+        reason: &str,
+        message: String,
+    ) -> Result<()> {
+        eprintln!("{}:{}: {}, skipping...", source, line, message);
+        if let Some(rejects) = self.rejects.as_mut() {
+            rejects.write(&Rejection {
+                source,
+                line,
+                reason,
+                record,
+                message,
+            })?;
+        }
         Ok(())
     }
 
     /// Attempts to handle a transaction based on its type.
     ///
+    /// If a fee schedule is set, a transaction that succeeds is charged
+    /// its fee, and a transaction that is charged back has the fees it
+    /// was charged reversed.
+    ///
+    /// If a write-ahead log is set, every transaction is written to the
+    /// log before it is applied, and is not applied if it can't be. A
+    /// crash at any point after leaves the entry to be replayed, and
+    /// entries that were rejected are rejected again on replay, as they
+    /// replay on top of the same state.
+    ///
+    /// A capture of an authorization whose hold has expired by the time
+    /// of the capture, which is its timestamp or else the current time,
+    /// expires the hold first and is then refused. An expiry of a hold
+    /// that hasn't expired by then is refused.
+    ///
     /// Returns an Err if the transaction fails (eg. invalid
-    /// transaction reference) or if the type is not implemented.
-    fn handle_transaction(&mut self, transaction: Transaction) -> Result<()> {
+    /// transaction reference), if the type is not implemented,
+    /// or if it can't be written to the log.
+    pub fn handle_transaction(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        let now = transaction.timestamp.unwrap_or_else(unix_now);
+        match transaction.get_type().as_str() {
+            "capture" => self.expire_hold_by(transaction.tx, now)?,
+            "expire" => {
+                if let Err(err) = self.check_expired(&transaction, now) {
+                    self.record(&transaction, &Err(err.clone()));
+                    return Err(err);
+                }
+            }
+            _ => {}
+        }
+        self.handle(transaction)
+    }
+
+    /// Checks that an expiry refers to a hold that has expired by `now`
+    /// in unix seconds. Holds without an expiry never expire. Expiries
+    /// that refer to anything else are left for `handle` to reject.
+    ///
+    /// Returns an Err if the hold is still held and hasn't expired, or
+    /// the logged transaction can't be read.
+    fn check_expired(&self, transaction: &Transaction, now: u64) -> Result<(), EngineError> {
+        let tx = transaction.tx;
+        let Some(logged) = self.transaction_log.get(tx).map_err(storage_error(tx))? else {
+            return Ok(());
+        };
+        match logged.hold {
+            Some(Hold {
+                state: HoldState::Authorized,
+                expires,
+            }) if logged.client == transaction.client => match expires {
+                Some(expires) if expires <= now => Ok(()),
+                _ => Err(EngineError::HoldNotExpired { tx }),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Handles a transaction like `handle_transaction`, without expiring
+    /// the hold a capture refers to first.
+    fn handle(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        let recorded = transaction.clone();
+        let result = self.apply_and_log(transaction);
+        self.record(&recorded, &result);
+        result
+    }
+
+    /// Writes a transaction to the write-ahead log if one is set, and
+    /// then applies it. See `handle_transaction`.
+    ///
+    /// Returns an Err if the transaction can't be written to the log, or
+    /// if it fails.
+    fn apply_and_log(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        if let Some(wal) = self.wal.as_mut() {
+            wal.append(&transaction)
+                .map_err(storage_error(transaction.tx))?;
+        }
+        self.apply_with_fees(transaction)
+    }
+
+    /// Records a handled transaction in the history of its client, and if
+    /// it was accepted, of the destination of the transfer it makes or
+    /// refers to. Nothing is recorded unless histories are kept.
+    pub(crate) fn record(&mut self, transaction: &Transaction, result: &Result<(), EngineError>) {
+        if self.history.is_none() {
+            return;
+        }
+        let destination = match (result, transaction.get_type().as_str()) {
+            (Ok(()), "transfer") => transaction.destination,
+            (Ok(()), "dispute" | "resolve" | "chargeback") => self
+                .transaction_log
+                .get(transaction.tx)
+                .ok()
+                .flatten()
+                .and_then(|logged| logged.destination),
+            _ => None,
+        };
+
+        for client in std::iter::once(transaction.client).chain(destination) {
+            let account = self.accounts.get(client);
+            let event = HistoryEvent::new(transaction, client, account, result.as_ref().err());
+            if let Some(history) = self.history.as_mut() {
+                history.entry(client).or_default().push(event);
+            }
+        }
+    }
+
+    /// Keeps a copy of the state a transaction may change, so that the
+    /// change can be undone if its fees can't be settled.
+    ///
+    /// Returns an Err if the logged transaction can't be read.
+    fn stage(&self, transaction: &Transaction) -> Result<Staged, EngineError> {
+        // A transaction only touches the account of its client, the
+        // destination of a transfer it makes or refers to, the logged
+        // transaction of its tx, and the end of the audit and fee logs
+        // and the ledger.
+        let tx = transaction.tx;
+        let logged = self.transaction_log.get(tx).map_err(storage_error(tx))?;
+        let accounts = std::iter::once(transaction.client)
+            .chain(transaction.destination)
+            .chain(logged.as_ref().and_then(|logged| logged.destination))
+            .map(|client| (client, self.accounts.get(client).cloned()))
+            .collect();
+        Ok(Staged {
+            tx,
+            logged,
+            accounts,
+            audited: self.audit.len(),
+            fees: self.fee_log.len(),
+            posted: self.ledger.as_ref().map_or(0, Ledger::len),
+        })
+    }
+
+    /// Puts back the state kept by `stage`. The store failing to undo
+    /// can't be reported beyond the error that caused the undo.
+    fn undo(&mut self, staged: Staged) {
+        for (client, account) in staged.accounts.into_iter().rev() {
+            match account {
+                Some(account) => self.accounts.insert(account),
+                None => self.accounts.remove(client),
+            };
+        }
+        let _ = match staged.logged {
+            Some(logged) => self.transaction_log.insert(staged.tx, logged),
+            None => self.transaction_log.remove(staged.tx),
+        };
+        self.audit.truncate(staged.audited);
+        self.fee_log.truncate(staged.fees);
+        if let Some(ledger) = self.ledger.as_mut() {
+            ledger.truncate(staged.posted);
+        }
+    }
+
+    /// Applies a transaction and settles its fees if a fee schedule is
+    /// set, undoing the transaction if its fees can't be settled.
+    ///
+    /// Returns an Err if the transaction or its fees fail.
+    fn apply_with_fees(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        let fee = match self.fee_for(&transaction)? {
+            Fee::Unscheduled => return self.apply_transaction(transaction),
+            fee => fee,
+        };
+
+        let staged = self.stage(&transaction)?;
+        let entry = transaction.clone();
+        self.apply_transaction(transaction)?;
+        if let Err(err) = self.settle_fees(&entry, fee) {
+            self.undo(staged);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Returns the fee the schedule charges for a transaction.
+    /// Transactions without an amount are charged on the portion of the
+    /// transaction they refer to that they act on.
+    ///
+    /// Returns an Err if the fee can't be worked out.
+    fn fee_for(&self, transaction: &Transaction) -> Result<Fee, EngineError> {
+        let Some(fees) = self.fees.as_ref() else {
+            return Ok(Fee::Unscheduled);
+        };
+
+        let (tx, kind) = (transaction.tx, transaction.get_type());
+        let references = matches!(
+            kind.as_str(),
+            "capture" | "release" | "expire" | "dispute" | "resolve" | "chargeback"
+        );
+        let amount = match transaction.amount {
+            Some(amount) => amount,
+            None if references => match self.transaction_log.get(tx).map_err(storage_error(tx))? {
+                Some(logged) => match kind.as_str() {
+                    "dispute" => self.disputable(&logged),
+                    "resolve" | "chargeback" => logged.disputed,
+                    _ => logged.amount,
+                },
+                None => Amount::default(),
+            },
+            None => Amount::default(),
+        };
+        Ok(match fees.fee(&kind, transaction.client, amount)? {
+            Some(fee) => Fee::Charge(fee),
+            None => Fee::Free,
+        })
+    }
+
+    /// Reverses the fees charged for a transaction if it was just charged
+    /// back, and charges the fee for handling it if there is one. Fees go
+    /// to and from the client of the transaction.
+    ///
+    /// Transactions that take funds out of the available funds must
+    /// leave enough for their fee, while other fees may leave the
+    /// available funds negative.
+    ///
+    /// Returns an Err if the available funds don't cover the fee of a
+    /// transaction that takes funds, or an account balance would
+    /// overflow.
+    fn settle_fees(&mut self, transaction: &Transaction, fee: Fee) -> Result<(), EngineError> {
+        let (tx, client, kind) = (transaction.tx, transaction.client, transaction.get_type());
+
+        let charged_back = kind == "chargeback"
+            && self
+                .transaction_log
+                .get(tx)
+                .map_err(storage_error(tx))?
+                .is_some_and(|logged| logged.state == DisputeState::ChargedBack);
+        if charged_back {
+            // Only the fees of the transaction itself are reversed, not
+            // those of its disputes.
+            let reversed: Vec<FeeEntry> = self
+                .fee_log
+                .iter()
+                .filter(|entry| entry.tx == tx && !entry.reversal)
+                .filter(|entry| {
+                    matches!(entry.r#type.as_str(), "deposit" | "withdrawal" | "transfer")
+                })
+                .cloned()
+                .collect();
+            for entry in reversed {
+                self.get_or_create_account(entry.client)
+                    .reverse_fee(entry.amount)?;
+                let to = LedgerAccount::Available(entry.client);
+                self.post(tx, LedgerAccount::Fees, to, entry.amount);
+                self.fee_log.push(FeeEntry {
+                    reversal: true,
+                    ..entry
+                });
+            }
+        }
+
+        if let Fee::Charge(amount) = fee {
+            // The funds taken are already out of the available funds.
+            let account = self.get_or_create_account(client);
+            if matches!(kind.as_str(), "withdrawal" | "transfer" | "authorize")
+                && account.available < amount
+            {
+                let taken = transaction.amount.unwrap_or_default();
+                return Err(EngineError::InsufficientFunds {
+                    client,
+                    requested: Amount::from_raw(taken.raw().saturating_add(amount.raw())),
+                    available: Amount::from_raw(account.available.raw() + taken.raw()),
+                });
+            }
+            account.charge_fee(amount)?;
+            let from = LedgerAccount::Available(client);
+            self.post(tx, from, LedgerAccount::Fees, amount);
+            self.fee_log.push(FeeEntry {
+                tx,
+                client,
+                r#type: kind,
+                amount,
+                reversal: false,
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies a transaction based on its type, without writing it to
+    /// the write-ahead log.
+    fn apply_transaction(&mut self, transaction: Transaction) -> Result<(), EngineError> {
         // Handle all capitalizations of the type.
         match transaction.get_type().as_str() {
             "deposit" => self.deposit(transaction),
             "withdrawal" => self.withdrawal(transaction),
+            "transfer" => self.transfer(transaction),
+            "authorize" => self.authorize(transaction),
+            "capture" => self.capture(transaction),
+            "release" => self.release(transaction),
+            "expire" => self.expire(transaction),
             "dispute" => self.dispute(transaction),
             "resolve" => self.resolve(transaction),
             "chargeback" => self.chargeback(transaction),
-            unknown => {
-                return Err(Error::msg(format!(
-                    "Transaction {} type '{}' not implemented",
-                    transaction.tx, unknown
-                )))
-            }
+            "unlock" => self.unlock(transaction),
+            "freeze" => self.freeze(transaction),
+            "close" => self.close(transaction),
+            unknown => Err(EngineError::UnsupportedType {
+                tx: transaction.tx,
+                kind: unknown.to_string(),
+            }),
         }
     }
 
     /// Attempts to perform a deposit into a related account.
     ///
-    /// Returns an Err if the transaction exists already or
-    /// an amount is not specified.
+    /// Returns an Err if the transaction exists already, a positive
+    /// amount is not specified, the total would overflow, or the account
+    /// is closed, or locked and the policy doesn't allow deposits into
+    /// locked accounts.
     ///
     /// This function does not validate transaction type and
     /// assumes all transactions passed to it are to be treated
     /// as deposits.
-    fn deposit(&mut self, transaction: Transaction) -> Result<()> {
+    pub(crate) fn deposit(&mut self, transaction: Transaction) -> Result<(), EngineError> {
         // If the transaction already exists, return.
-        if self.transaction_log.contains_key(&transaction.tx) {
-            return Err(Error::msg(format!(
-                "[deposit] Transaction {} already exists",
-                transaction.tx
-            )));
+        let logged = self.transaction_log.get(transaction.tx);
+        if logged.map_err(storage_error(transaction.tx))?.is_some() {
+            return Err(EngineError::DuplicateTransaction { tx: transaction.tx });
         }
 
         // Get the relevant account or create a new one so we can manipulate it.
+        let locked_deposits = self.policy.locked_deposits;
         let account = self.get_or_create_account(transaction.client);
 
-        // Return early if an amount isn't specified on the transaction.
-        let amount = transaction.amount.context(format!(
-            "[deposit] Transaction {} did not specify amount",
-            transaction.tx
-        ))?;
+        // Return early if a positive amount isn't specified on the transaction.
+        let amount = positive_amount(&transaction)?;
+
+        // Locked accounts only take deposits if the policy allows it.
+        if !locked_deposits {
+            account.check_unlocked()?;
+        }
 
-        // Deposit the funds
-        account.deposit(amount);
+        // Deposit the funds, failing if the balance or total would overflow
+        // or the account is closed.
+        account.deposit(amount)?;
+        let to = LedgerAccount::Available(transaction.client);
+        self.post(transaction.tx, LedgerAccount::Cash, to, amount);
 
         // Log for future reference. This shouldn't error if above amount didn't
         self.log_transaction(transaction)?;
@@ -247,124 +1345,571 @@ impl Bank {
 
     /// Attempts to perform a withdrawal from a related account.
     ///
-    /// Returns an Err if an amount is not specified, the account
+    /// Returns an Err if a positive amount is not specified, the account
     /// does not have sufficient funds, or the account is locked.
     ///
     /// This function does not validate transaction type and
     /// assumes all transactions passed to it are to be treated
     /// as withdrawals.
-    fn withdrawal(&mut self, transaction: Transaction) -> Result<()> {
+    pub(crate) fn withdrawal(&mut self, transaction: Transaction) -> Result<(), EngineError> {
         // If the transaction already exists, return.
-        if self.transaction_log.contains_key(&transaction.tx) {
-            return Err(Error::msg(format!(
-                "[withdrawal] Transaction {} already exists",
-                transaction.tx
-            )));
+        let logged = self.transaction_log.get(transaction.tx);
+        if logged.map_err(storage_error(transaction.tx))?.is_some() {
+            return Err(EngineError::DuplicateTransaction { tx: transaction.tx });
         }
 
-        // Return early if an amount isn't specified on the transaction.
-        let amount = transaction.amount.context(format!(
-            "[withdrawal] Transaction {} did not specify amount",
-            transaction.tx
-        ))?;
+        // Return early if a positive amount isn't specified on the transaction.
+        let amount = positive_amount(&transaction)?;
 
         // Get the relevant account or create a new one so we can manipulate it.
         let account = self.get_or_create_account(transaction.client);
 
         // Attempts to withdraw from the account, returning early if the
         // withdrawal fails due to lack of funds or the account is locked.
-        account.withdraw(amount).context(format!(
-            "[withdrawal] Transaction {} failed",
-            transaction.tx
-        ))?;
+        account.withdraw(amount)?;
+        let from = LedgerAccount::Available(transaction.client);
+        self.post(transaction.tx, from, LedgerAccount::Cash, amount);
 
         // Log for future reference. This shouldn't error if above amount didn't
         self.log_transaction(transaction)?;
         Ok(())
     }
 
-    /// Attempts to dispute a related transaction.
+    /// Attempts to move funds from a related account to the account of
+    /// a destination client, creating the destination account if needed.
+    /// Either both accounts change or neither does.
+    ///
+    /// Returns an Err if the transaction exists already, a positive amount or
+    /// destination is not specified, the destination is the same client,
+    /// the account does not have sufficient funds, either account is
+    /// locked, or the destination balance would overflow.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as transfers.
+    pub(crate) fn transfer(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        let tx = transaction.tx;
+
+        // If the transaction already exists, return.
+        if self
+            .transaction_log
+            .get(tx)
+            .map_err(storage_error(tx))?
+            .is_some()
+        {
+            return Err(EngineError::DuplicateTransaction { tx });
+        }
+
+        // Return early if a positive amount or a destination isn't specified.
+        let amount = positive_amount(&transaction)?;
+        let destination = transaction
+            .destination
+            .ok_or(EngineError::MissingDestination { tx })?;
+        if destination == transaction.client {
+            return Err(EngineError::SelfTransfer {
+                tx,
+                client: destination,
+            });
+        }
+
+        // Check the destination can take the funds before moving them.
+        if let Some(account) = self.accounts.get(destination) {
+            account.check_unlocked()?;
+        }
+
+        // Withdraw from the source, failing if it lacks funds or is locked.
+        self.get_or_create_account(transaction.client)
+            .withdraw(amount)?;
+
+        // Deposit into the destination, putting the funds back if its
+        // balance would overflow. They were just withdrawn, so putting
+        // them back can't fail.
+        if let Err(err) = self.get_or_create_account(destination).deposit(amount) {
+            let _ = self
+                .get_or_create_account(transaction.client)
+                .deposit(amount);
+            return Err(err);
+        }
+        let from = LedgerAccount::Available(transaction.client);
+        self.post(tx, from, LedgerAccount::Available(destination), amount);
+
+        // Log with the destination so the transfer can be disputed.
+        self.log_transaction(transaction)?;
+        Ok(())
+    }
+
+    /// Attempts to authorize funds in a related account for a later
+    /// capture, holding them apart from disputed funds. The authorization
+    /// expires at `transaction.expires` if it is set.
+    ///
+    /// Returns an Err if the transaction exists already, a positive amount
+    /// is not specified, the account does not have sufficient funds, or the
+    /// account is locked.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as authorizations.
+    pub(crate) fn authorize(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        let tx = transaction.tx;
+
+        // If the transaction already exists, return.
+        if self
+            .transaction_log
+            .get(tx)
+            .map_err(storage_error(tx))?
+            .is_some()
+        {
+            return Err(EngineError::DuplicateTransaction { tx });
+        }
+
+        // Return early if a positive amount isn't specified on the transaction.
+        let amount = positive_amount(&transaction)?;
+
+        // Set the funds aside, failing if they aren't available.
+        self.get_or_create_account(transaction.client)
+            .authorize(amount)?;
+        let client = transaction.client;
+        let (from, to) = (
+            LedgerAccount::Available(client),
+            LedgerAccount::Authorized(client),
+        );
+        self.post(tx, from, to, amount);
+
+        if let Some(expires) = transaction.expires {
+            self.expiring.insert((expires, tx));
+        }
+        self.log_transaction(transaction)?;
+        Ok(())
+    }
+
+    /// Attempts to capture a related authorization, taking the
+    /// authorized funds from the account like a withdrawal.
+    ///
+    /// Returns an Err if the related authorization is invalid or
+    /// no longer held, or the account is locked.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as captures.
+    pub(crate) fn capture(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        let in_question = self.validate_hold_reference(&transaction)?;
+
+        self.get_or_create_account(transaction.client)
+            .capture(in_question.amount)?;
+        let from = LedgerAccount::Authorized(transaction.client);
+        self.post(
+            transaction.tx,
+            from,
+            LedgerAccount::Cash,
+            in_question.amount,
+        );
+
+        self.set_hold_state(transaction.tx, HoldState::Captured)?;
+        Ok(())
+    }
+
+    /// Attempts to release a related authorization, returning the
+    /// authorized funds to available.
+    ///
+    /// Returns an Err if the related authorization is invalid or
+    /// no longer held.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as releases.
+    pub(crate) fn release(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        self.end_hold(transaction, HoldState::Released)
+    }
+
+    /// Attempts to expire a related authorization, returning the
+    /// authorized funds to available like a release. These are
+    /// created by `expire_holds` and captures, and checked by
+    /// `handle_transaction` when they come from an input, which all
+    /// check the expiry first.
+    ///
+    /// Returns an Err if the related authorization is invalid or
+    /// no longer held.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as expiries.
+    pub(crate) fn expire(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        self.end_hold(transaction, HoldState::Expired)
+    }
+
+    /// Returns the funds of a related authorization to available,
+    /// ending its hold in a state.
+    ///
+    /// Returns an Err if the related authorization is invalid or
+    /// no longer held.
+    fn end_hold(&mut self, transaction: Transaction, state: HoldState) -> Result<(), EngineError> {
+        let in_question = self.validate_hold_reference(&transaction)?;
+
+        self.get_or_create_account(transaction.client)
+            .release(in_question.amount)?;
+        let client = transaction.client;
+        let (from, to) = (
+            LedgerAccount::Authorized(client),
+            LedgerAccount::Available(client),
+        );
+        self.post(transaction.tx, from, to, in_question.amount);
+
+        self.set_hold_state(transaction.tx, state)?;
+        Ok(())
+    }
+
+    /// Attempts to dispute a related transaction. Disputing a deposit
+    /// holds the deposited funds, while disputing a withdrawal (if the
+    /// policy allows it) holds the withdrawn amount as a provisional credit.
+    /// Disputing a transfer holds the funds in the destination account.
+    /// A dispute with an amount only disputes that much of what is left
+    /// undisputed, so a transaction can be disputed in parts.
     ///
     /// Returns an Err if the related transaction is invalid
-    /// or already disputed, or the account is locked.
+    /// or already disputed, the amount is more than is left undisputed,
+    /// or the account is locked.
     ///
     /// This function does not validate transaction type and
     /// assumes all transactions passed to it are to be treated
     /// as disputes.
-    fn dispute(&mut self, transaction: Transaction) -> Result<()> {
-        // Check referenced transaction for sanity and grab the amount.
-        let amount = self
-            .validate_transaction_reference(&transaction, true)
-            .context("[dispute] Bad reference")?
-            .amount;
-
-        // Get the account for manipulation.
-        let account = self.get_or_create_account(transaction.client);
+    pub(crate) fn dispute(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        // Check referenced transaction for sanity and grab the amount,
+        // which is whatever is left to dispute unless the dispute names less.
+        let mut in_question =
+            self.validate_transaction_reference(&transaction, DisputeState::Disputed)?;
+        let amount = dispute_portion(&transaction, self.disputable(&in_question))?;
 
-        // Attempt to process the dispute, failing if the account is locked.
-        account
-            .dispute(amount)
-            .context(format!("[dispute] Transaction {} failed", transaction.tx))?;
+        // Disputes only come within the window when the disputed
+        // transaction says when it happened. A dispute that doesn't say
+        // when it happened is taken to be as late as the latest timestamp
+        // seen.
+        if let (Some(window), Some(disputed_at), Some(happened_at)) = (
+            self.policy.dispute_window,
+            transaction.timestamp.or(self.latest_timestamp),
+            in_question.timestamp,
+        ) {
+            if disputed_at.saturating_sub(happened_at) > window {
+                return Err(EngineError::DisputeWindowExpired {
+                    tx: transaction.tx,
+                    window,
+                });
+            }
+        }
 
-        // Mark the transaction for dispute.
-        self.set_disputed(transaction.tx, true)
-            .context("[dispute] Can't set dispute")?;
-        Ok(())
+        let client = transaction.client;
+        let (from, to) = if let Some(destination) = in_question.destination {
+            // Transferred funds are held where they went to.
+            self.get_or_create_account(destination).dispute(amount)?;
+            (
+                LedgerAccount::Available(destination),
+                LedgerAccount::Held(destination),
+            )
+        } else {
+            // Get the account for manipulation.
+            let account = self.get_or_create_account(client);
+
+            // Attempt to process the dispute, failing if the account is locked.
+            if in_question.deposit {
+                account.dispute(amount)?;
+                (
+                    LedgerAccount::Available(client),
+                    LedgerAccount::Held(client),
+                )
+            } else {
+                // The provisional credit is paid out by the bank.
+                account.dispute_withdrawal(amount)?;
+                (LedgerAccount::Chargebacks, LedgerAccount::Held(client))
+            }
+        };
+        self.post(transaction.tx, from, to, amount);
+
+        // Mark the transaction for dispute, taking the portion from what
+        // was never disputed before what was resolved. The portions can't
+        // overflow as they stay within the transaction's amount.
+        let redisputed = amount.raw() - amount.raw().min(in_question.never_disputed().raw());
+        in_question.resolved = Amount::from_raw(in_question.resolved.raw() - redisputed);
+        in_question.disputed = Amount::from_raw(in_question.disputed.raw() + amount.raw());
+        in_question.state = DisputeState::Disputed;
+        self.update_dispute(transaction.tx, in_question)
     }
 
-    /// Attempts to resolve a related transaction.
+    /// Attempts to resolve a related transaction. Resolving a transfer
+    /// releases the funds held in the destination account. A resolve
+    /// with an amount only releases that much of the disputed portion,
+    /// and the transaction stays Disputed until none of it is.
     ///
     /// Returns an Err if the related transaction is invalid
-    /// or not disputed, or the account is locked.
+    /// or not disputed, the amount is more than is disputed, or the
+    /// account is frozen or closed. Accounts locked by a chargeback can
+    /// still resolve disputes that were open when they were locked.
     ///
     /// This function does not validate transaction type and
     /// assumes all transactions passed to it are to be treated
     /// as a resolve.
-    fn resolve(&mut self, transaction: Transaction) -> Result<()> {
-        // Check referenced transaction for sanity and grab the amount.
-        let amount = self
-            .validate_transaction_reference(&transaction, false)
-            .context("[resolve] Bad reference")?
-            .amount;
+    pub(crate) fn resolve(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        // Check referenced transaction for sanity and grab the amount,
+        // which is all of the disputed portion unless the resolve names less.
+        let mut in_question =
+            self.validate_transaction_reference(&transaction, DisputeState::Resolved)?;
+        let amount = dispute_portion(&transaction, in_question.disputed)?;
 
-        let account = self.get_or_create_account(transaction.client);
+        let client = transaction.client;
+        let (from, to) = if let Some(destination) = in_question.destination {
+            self.get_or_create_account(destination).resolve(amount)?;
+            (
+                LedgerAccount::Held(destination),
+                LedgerAccount::Available(destination),
+            )
+        } else {
+            let account = self.get_or_create_account(client);
 
-        // Attempt to resolve disputed funds, failing if the account is locked.
-        account
-            .resolve(amount)
-            .context(format!("[resolve] Transaction {} failed", transaction.tx))?;
+            // Attempt to resolve disputed funds, failing if the account is frozen or closed.
+            if in_question.deposit {
+                account.resolve(amount)?;
+                (
+                    LedgerAccount::Held(client),
+                    LedgerAccount::Available(client),
+                )
+            } else {
+                account.resolve_withdrawal(amount)?;
+                (LedgerAccount::Held(client), LedgerAccount::Chargebacks)
+            }
+        };
+        self.post(transaction.tx, from, to, amount);
 
-        // The transaction is no longer disputed.
-        self.set_disputed(transaction.tx, false)
-            .context("[resolve] Can't set dispute")?;
-        Ok(())
+        // The transaction is no longer disputed once none of it is.
+        in_question.disputed = Amount::from_raw(in_question.disputed.raw() - amount.raw());
+        in_question.resolved = Amount::from_raw(in_question.resolved.raw() + amount.raw());
+        if in_question.disputed.is_zero() {
+            in_question.state = DisputeState::Resolved;
+        }
+        self.update_dispute(transaction.tx, in_question)
     }
 
-    /// Attempts to chargeback a related transaction. This does
-    /// not clear the transaction of its disputed status.
+    /// Attempts to chargeback a related transaction. A chargeback with
+    /// an amount only charges back that much of the disputed portion.
+    /// The transaction is ChargedBack once none of it is disputed, and
+    /// can't be disputed again. Charging back a transfer returns the
+    /// held funds from the destination account to the source, and
+    /// locks the destination.
     ///
     /// Returns an Err if the related transaction is invalid
-    /// or not disputed, or the account is locked.
+    /// or not disputed, the amount is more than is disputed, or the
+    /// account is frozen or closed. A partial chargeback locks the
+    /// account, but the rest of the dispute can still be settled.
     ///
     /// This function does not validate transaction type and
     /// assumes all transactions passed to it are to be treated
     /// as a chargeback.
-    fn chargeback(&mut self, transaction: Transaction) -> Result<()> {
-        // Check referenced transaction for sanity and grab the amount.
-        let amount = self
-            .validate_transaction_reference(&transaction, false)
-            .context("[chargeback] Bad reference")?
-            .amount;
-
-        // Get the account for manipulation.
-        let account = self.get_or_create_account(transaction.client);
+    pub(crate) fn chargeback(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        // Check referenced transaction for sanity and grab the amount,
+        // which is all of the disputed portion unless the chargeback
+        // names less.
+        let mut in_question =
+            self.validate_transaction_reference(&transaction, DisputeState::ChargedBack)?;
+        let amount = dispute_portion(&transaction, in_question.disputed)?;
+
+        let client = transaction.client;
+        let (from, to) = if let Some(destination) = in_question.destination {
+            self.chargeback_transfer(client, destination, amount)?;
+            (
+                LedgerAccount::Held(destination),
+                LedgerAccount::Available(client),
+            )
+        } else {
+            // Get the account for manipulation.
+            let account = self.get_or_create_account(client);
+
+            // Attempt to chargeback funds, failing if the account is frozen or closed.
+            if in_question.deposit {
+                account.chargeback(amount)?;
+                (LedgerAccount::Held(client), LedgerAccount::Chargebacks)
+            } else {
+                account.chargeback_withdrawal(amount)?;
+                (
+                    LedgerAccount::Held(client),
+                    LedgerAccount::Available(client),
+                )
+            }
+        };
+        self.post(transaction.tx, from, to, amount);
+
+        // The dispute is over for good once none of it is disputed.
+        in_question.disputed = Amount::from_raw(in_question.disputed.raw() - amount.raw());
+        in_question.charged_back = Amount::from_raw(in_question.charged_back.raw() + amount.raw());
+        if in_question.disputed.is_zero() {
+            in_question.state = DisputeState::ChargedBack;
+        }
+        self.update_dispute(transaction.tx, in_question)
+    }
 
-        // Attempt to chargeback funds, failing if the account is locked.
-        account.chargeback(amount).context(format!(
-            "[chargeback] Transaction {} failed",
-            transaction.tx
-        ))?;
+    /// Attempts to unlock a client's account that was locked by a
+    /// chargeback or frozen, recording it in the audit log.
+    ///
+    /// Returns an Err if admin operations are not allowed, the actor is
+    /// not specified, or the account does not exist, is not locked or is
+    /// closed.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as unlocks.
+    pub(crate) fn unlock(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        self.administer(transaction, AdminAction::Unlock)
+    }
 
+    /// Attempts to freeze a client's account, so that it can't be used
+    /// until it is unlocked, recording it in the audit log.
+    ///
+    /// Returns an Err if admin operations are not allowed, the actor or
+    /// reason is not specified, or the account does not exist or is
+    /// closed.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as freezes.
+    pub(crate) fn freeze(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        self.administer(transaction, AdminAction::Freeze)
+    }
+
+    /// Attempts to close a client's account for good, recording it in
+    /// the audit log.
+    ///
+    /// Returns an Err if admin operations are not allowed, the actor is
+    /// not specified, or the account does not exist, still has funds or
+    /// is already closed.
+    ///
+    /// This function does not validate transaction type and
+    /// assumes all transactions passed to it are to be treated
+    /// as closes.
+    pub(crate) fn close(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        self.administer(transaction, AdminAction::Close)
+    }
+
+    /// Applies an admin action to a client's account and records it,
+    /// along with who made it and why, in the audit log.
+    ///
+    /// Returns an Err if the action can't be applied.
+    fn administer(
+        &mut self,
+        transaction: Transaction,
+        action: AdminAction,
+    ) -> Result<(), EngineError> {
+        let (tx, client) = (transaction.tx, transaction.client);
+        if !self.policy.allow_admin {
+            return Err(EngineError::AdminDisabled { tx });
+        }
+        let actor = transaction.actor.ok_or(EngineError::MissingActor { tx })?;
+        if action == AdminAction::Freeze && transaction.reason.is_none() {
+            return Err(EngineError::MissingReason { tx });
+        }
+
+        // Admin actions never open an account.
+        if self.accounts.get(client).is_none() {
+            return Err(EngineError::UnknownAccount { client });
+        }
+        let account = self.get_or_create_account(client);
+        let previous = account.status;
+        match action {
+            AdminAction::Unlock => account.unlock()?,
+            AdminAction::Freeze => account.freeze()?,
+            AdminAction::Close => account.close()?,
+        }
+
+        self.audit.push(AuditEntry {
+            tx,
+            client,
+            action,
+            actor,
+            reason: transaction.reason,
+            previous,
+        });
         Ok(())
     }
+
+    /// Removes held funds from the destination of a transfer, locking
+    /// it, and returns them to the source account.
+    ///
+    /// Returns an Err if the destination does not hold enough funds or
+    /// is locked, or the source balance would overflow.
+    fn chargeback_transfer(
+        &mut self,
+        source: u16,
+        destination: u16,
+        amount: Amount,
+    ) -> Result<(), EngineError> {
+        let before = self.accounts.get(destination).cloned();
+        self.get_or_create_account(destination).chargeback(amount)?;
+
+        if let Err(err) = self.get_or_create_account(source).deposit(amount) {
+            // Undo the chargeback. The destination held the funds, so it
+            // existed before.
+            if let Some(before) = before {
+                self.accounts.insert(before);
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the amount of a transaction that moves funds.
+///
+/// Returns an Err if the amount is not specified or is not positive.
+fn positive_amount(transaction: &Transaction) -> Result<Amount, EngineError> {
+    let tx = transaction.tx;
+    let amount = transaction
+        .amount
+        .ok_or(EngineError::MissingAmount { tx })?;
+    if amount <= Amount::default() {
+        return Err(EngineError::InvalidAmount { tx, amount });
+    }
+    Ok(amount)
+}
+
+/// Returns the amount a dispute, resolve or chargeback acts on, which is
+/// all of `limit` unless the transaction names less.
+///
+/// Returns an Err if the named amount is not positive or is more than
+/// `limit`.
+fn dispute_portion(transaction: &Transaction, limit: Amount) -> Result<Amount, EngineError> {
+    let requested = transaction.amount.unwrap_or(limit);
+    if requested <= Amount::default() || requested > limit {
+        return Err(EngineError::InvalidDisputeAmount {
+            tx: transaction.tx,
+            requested,
+            limit,
+        });
+    }
+    Ok(requested)
+}
+
+/// The fee a transaction is charged, see `Bank::fee_for`.
+enum Fee {
+    /// No fee schedule is set, so fees are neither charged nor reversed.
+    Unscheduled,
+    /// The schedule has no fee for the transaction, though it may still
+    /// reverse the fees of a transaction it charges back.
+    Free,
+    /// The schedule charges the fee.
+    Charge(Amount),
+}
+
+/// The state a transaction may change, kept by `Bank::stage` so that
+/// the change can be undone.
+struct Staged {
+    tx: u32,
+    logged: Option<LoggedTransaction>,
+    accounts: Vec<(u16, Option<Account>)>,
+    audited: usize,
+    fees: usize,
+    posted: usize,
+}
+
+/// Returns a function that turns a failure of the transaction store
+/// into an EngineError for a transaction.
+fn storage_error(tx: u32) -> impl FnOnce(Error) -> EngineError {
+    move |err| EngineError::Storage {
+        tx,
+        message: err.to_string(),
+    }
 }
@@ -0,0 +1,241 @@
+use anyhow::{Context, Error, Result};
+use payengine::decoder::InputFormat;
+use payengine::query::AsOf;
+use payengine::rejects::RejectsFormat;
+use payengine::report::{OutputFormat, SortKey, SortOrder};
+use payengine::wal::DEFAULT_SYNC_EVERY;
+use payengine::Policy;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: payengine <transactions.csv|->... [--input-format csv|ndjson] \
+[--state-in <path>] [--state-out <path>] [--wal <path>] [--wal-sync-every <n>] \
+[--tx-store <path>] [--threads <n>] [--serve <address>] [--dispute-withdrawals] [--no-redispute] \
+[--no-locked-deposits] [--dispute-window <seconds>] [--allow-admin] [--fees <path>] \
+[--verify-ledger] [--keep-history] [--as-of-line <input>:<line>] [--as-of-tx <tx>] \
+[--rejects <path>] [--rejects-format csv|ndjson] [--output-format csv|json|ndjson] \
+[--sort client|total|available|held] [--order asc|desc]
+       payengine statement <client> <transactions.csv|->... [options]
+       payengine balance <client> <transactions.csv|->... [options]";
+
+/// What the binary prints once the inputs are processed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    /// The report of every account.
+    #[default]
+    Report,
+    /// The statement of a client.
+    Statement(u16),
+    /// The balances of a client's account.
+    Balance(u16),
+}
+
+/// Options holds the command line arguments of the binary.
+pub struct Options {
+    /// What to print once the inputs are processed.
+    pub command: Command,
+    /// The transaction files to process in order, where `-` is stdin.
+    pub inputs: Vec<PathBuf>,
+    /// The format of the inputs. Guessed from each path when not
+    /// specified, and CSV for stdin.
+    pub input_format: Option<InputFormat>,
+    /// A snapshot to continue from, if any.
+    pub state_in: Option<PathBuf>,
+    /// Where to save a snapshot after processing, if anywhere.
+    pub state_out: Option<PathBuf>,
+    /// A write-ahead log to replay and then append to, if any.
+    pub wal: Option<PathBuf>,
+    /// The number of write-ahead log entries written between syncs.
+    pub wal_sync_every: usize,
+    /// A file to keep the transaction log in instead of memory, if any.
+    pub tx_store: Option<PathBuf>,
+    /// The number of threads to shard processing over.
+    pub threads: usize,
+    /// An address to serve the bank on after processing the inputs,
+    /// instead of printing the report.
+    pub serve: Option<String>,
+    /// The rules the bank applies to transactions.
+    pub policy: Policy,
+    /// A fee schedule to charge transactions from, if any.
+    pub fees: Option<PathBuf>,
+    /// Whether to keep a ledger and check it against the accounts after
+    /// processing.
+    pub verify_ledger: bool,
+    /// Whether to keep the history of every client, so it is saved in
+    /// the state for statements in later runs. Always kept for a
+    /// statement.
+    pub keep_history: bool,
+    /// The point in the inputs to stop processing at, if any.
+    pub as_of: Option<AsOf>,
+    /// Where to write rejected rows, if anywhere.
+    pub rejects: Option<PathBuf>,
+    /// The format to write rejected rows in. Guessed from the
+    /// rejects path when not specified.
+    pub rejects_format: Option<RejectsFormat>,
+    /// The format to print the report in.
+    pub output_format: OutputFormat,
+    /// The account field to sort the report by.
+    pub sort: SortKey,
+    /// The direction to sort the report in.
+    pub order: SortOrder,
+}
+
+impl Options {
+    /// Parses Options from command line arguments, not including
+    /// the program name.
+    ///
+    /// A first argument of `statement` or `balance` and a client ID
+    /// prints that client's statement or balances instead of the report.
+    ///
+    /// Returns an Err if an option is unknown, missing its value,
+    /// no input file is specified when not serving, or options that
+    /// can't be combined are.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter().peekable();
+        let mut command = Command::default();
+        if let Some(name) = args.next_if(|arg| arg == "statement" || arg == "balance") {
+            let client = value(&mut args, &name)?;
+            let client = client
+                .parse()
+                .with_context(|| format!("Invalid client '{}'\n{}", client, USAGE))?;
+            command = match name.as_str() {
+                "statement" => Command::Statement(client),
+                _ => Command::Balance(client),
+            };
+        }
+
+        let mut inputs = vec![];
+        let mut input_format = None;
+        let mut state_in = None;
+        let mut state_out = None;
+        let mut wal = None;
+        let mut wal_sync_every = DEFAULT_SYNC_EVERY;
+        let mut tx_store = None;
+        let mut threads = 1;
+        let mut serve = None;
+        let mut policy = Policy::default();
+        let mut fees = None;
+        let mut verify_ledger = false;
+        let mut keep_history = matches!(command, Command::Statement(_));
+        let mut as_of = None;
+        let mut rejects = None;
+        let mut rejects_format = None;
+        let mut output_format = OutputFormat::default();
+        let mut sort = SortKey::default();
+        let mut order = SortOrder::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--input-format" => input_format = Some(value(&mut args, &arg)?.parse()?),
+                "--state-in" => state_in = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--state-out" => state_out = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--wal" => wal = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--wal-sync-every" => wal_sync_every = value(&mut args, &arg)?.parse()?,
+                "--tx-store" => tx_store = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--threads" => threads = value(&mut args, &arg)?.parse()?,
+                "--serve" => serve = Some(value(&mut args, &arg)?),
+                "--dispute-withdrawals" => policy.dispute_withdrawals = true,
+                "--no-redispute" => policy.redispute_resolved = false,
+                "--no-locked-deposits" => policy.locked_deposits = false,
+                "--dispute-window" => {
+                    policy.dispute_window = Some(value(&mut args, &arg)?.parse()?)
+                }
+                "--allow-admin" => policy.allow_admin = true,
+                "--fees" => fees = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--verify-ledger" => verify_ledger = true,
+                "--keep-history" => keep_history = true,
+                "--as-of-line" => as_of = Some(as_of_line(&value(&mut args, &arg)?)?),
+                "--as-of-tx" => as_of = Some(AsOf::Tx(value(&mut args, &arg)?.parse()?)),
+                "--rejects" => rejects = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--rejects-format" => rejects_format = Some(value(&mut args, &arg)?.parse()?),
+                "--output-format" => output_format = value(&mut args, &arg)?.parse()?,
+                "--sort" => sort = value(&mut args, &arg)?.parse()?,
+                "--order" => order = value(&mut args, &arg)?.parse()?,
+                flag if flag.starts_with("--") => {
+                    return Err(Error::msg(format!("Unknown option '{}'\n{}", flag, USAGE)))
+                }
+                _ => inputs.push(PathBuf::from(arg)),
+            }
+        }
+
+        // A client's statement or balances can be printed from a
+        // snapshot alone.
+        let from_state = command != Command::Report && state_in.is_some();
+        if inputs.is_empty() && serve.is_none() && !from_state {
+            return Err(Error::msg(format!(
+                "No file specified as first argument. Please specify a file.\n{}",
+                USAGE
+            )));
+        }
+
+        // Shards keep their state in memory, and aren't logged.
+        if threads > 1 && (wal.is_some() || tx_store.is_some()) {
+            return Err(Error::msg(format!(
+                "--threads can't be combined with --wal or --tx-store\n{}",
+                USAGE
+            )));
+        }
+
+        // A server runs until it's stopped, and never gets to the end.
+        if serve.is_some() && (threads > 1 || state_out.is_some() || command != Command::Report) {
+            return Err(Error::msg(format!(
+                "--serve can't be combined with --threads, --state-out, \
+                statement or balance\n{}",
+                USAGE
+            )));
+        }
+
+        // The state as of a point in the inputs is only looked at, as
+        // saving it would lose the rest of the inputs.
+        if as_of.is_some() && (wal.is_some() || state_out.is_some() || serve.is_some()) {
+            return Err(Error::msg(format!(
+                "--as-of-line and --as-of-tx can't be combined with --wal, \
+                --state-out or --serve\n{}",
+                USAGE
+            )));
+        }
+
+        Ok(Self {
+            command,
+            inputs,
+            input_format,
+            state_in,
+            state_out,
+            wal,
+            wal_sync_every,
+            tx_store,
+            threads,
+            serve,
+            policy,
+            fees,
+            verify_ledger,
+            keep_history,
+            as_of,
+            rejects,
+            rejects_format,
+            output_format,
+            sort,
+            order,
+        })
+    }
+}
+
+/// Takes the value following an option.
+fn value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String> {
+    args.next()
+        .context(format!("Option '{}' requires a value\n{}", option, USAGE))
+}
+
+/// Parses the `<input>:<line>` value of `--as-of-line`, where the input
+/// is named as it is in the arguments.
+fn as_of_line(value: &str) -> Result<AsOf> {
+    let (input, line) = value.rsplit_once(':').with_context(|| {
+        format!(
+            "Invalid line '{}', expected <input>:<line>\n{}",
+            value, USAGE
+        )
+    })?;
+    Ok(AsOf::Line {
+        input: input.to_string(),
+        line: line.parse()?,
+    })
+}
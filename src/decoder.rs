@@ -0,0 +1,251 @@
+use crate::transaction::Transaction;
+use anyhow::{Error, Result};
+use csv::{ByteRecord, ByteRecordsIntoIter, StringRecord, Trim};
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The format transactions are read in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Comma separated values with a header row.
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl InputFormat {
+    /// Guesses the format from a file extension, defaulting to CSV.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("ndjson") | Some("jsonl") => Self::Ndjson,
+            _ => Self::Csv,
+        }
+    }
+
+    /// Creates a Decoder of this format over a reader.
+    ///
+    /// Returns an Err if the input can't be read.
+    pub fn decoder<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Decoder + 'a>> {
+        Ok(match self {
+            Self::Csv => Box::new(CsvDecoder::new(reader)?),
+            Self::Ndjson => Box::new(NdjsonDecoder::new(reader)),
+        })
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            unknown => Err(Error::msg(format!("Unknown input format '{}'", unknown))),
+        }
+    }
+}
+
+/// A DecodedRow is a single row of input, which either decoded
+/// into a Transaction or failed to with a message.
+pub struct DecodedRow {
+    /// The line of the input the row started on.
+    pub line: u64,
+    /// The decoded transaction, or why it failed to decode.
+    pub transaction: Result<Transaction, String>,
+    /// The row as it was read, kept around for reporting rejections.
+    pub raw: RawRow,
+}
+
+/// A RawRow is a row as it was read from the input.
+pub struct RawRow(Raw);
+
+enum Raw {
+    Record(ByteRecord),
+    Line(String),
+}
+
+impl RawRow {
+    /// Returns the row as text. Bytes that aren't valid UTF-8 are
+    /// replaced.
+    pub fn text(&self) -> String {
+        match &self.0 {
+            Raw::Record(record) => {
+                // Rebuild the row through csv so fields are quoted as needed.
+                let mut writer = csv::Writer::from_writer(vec![]);
+                // Writing to a Vec can't fail.
+                let _ = writer.write_byte_record(record);
+                let bytes = writer.into_inner().unwrap_or_default();
+                String::from_utf8_lossy(&bytes).trim_end().to_string()
+            }
+            Raw::Line(line) => line.clone(),
+        }
+    }
+}
+
+/// A Decoder reads rows of some input format and decodes them into
+/// Transactions, one row at a time so that the entire input isn't
+/// loaded into memory at once.
+pub trait Decoder {
+    /// Decodes the next row, returning None at the end of the input.
+    ///
+    /// Returns an Err if the input can no longer be read. Rows that
+    /// are malformed are not an Err, see `DecodedRow::transaction`.
+    fn next_row(&mut self) -> Option<Result<DecodedRow>>;
+}
+
+impl<D: Decoder + ?Sized> Decoder for Box<D> {
+    fn next_row(&mut self) -> Option<Result<DecodedRow>> {
+        (**self).next_row()
+    }
+}
+
+/// Decodes CSV with a header row:
+/// ```text
+/// type, client, tx, amount
+/// deposit, 1, 1, 1.0
+/// DePosit, 1, 1,
+/// ```
+/// The CSV is trimmed of any whitespaces and allows a variable
+/// number of fields to allow amounts to be ignored.
+///
+/// Rows are read as bytes, so a row that isn't valid UTF-8 is still
+/// kept as it was read when it is rejected.
+///
+/// Note: The csv library does not support UTF16.
+pub struct CsvDecoder<R: Read> {
+    headers: StringRecord,
+    records: ByteRecordsIntoIter<R>,
+}
+
+impl<R: Read> CsvDecoder<R> {
+    /// Create a CsvDecoder, reading the header row from the reader.
+    ///
+    /// Returns an Err if the header row can't be read.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .from_reader(reader);
+        let headers = reader.headers()?.clone();
+
+        Ok(Self {
+            headers,
+            records: reader.into_byte_records(),
+        })
+    }
+}
+
+impl<R: Read> Decoder for CsvDecoder<R> {
+    fn next_row(&mut self) -> Option<Result<DecodedRow>> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            // The reader itself failing is fatal.
+            Err(err) if err.is_io_error() => return Some(Err(err.into())),
+            Err(err) => {
+                // Rows that can't be read at all still count as a row. This
+                // reader is flexible and reads bytes, so it shouldn't happen,
+                // and the error doesn't carry the row to keep.
+                return Some(Ok(DecodedRow {
+                    line: err.position().map_or(0, |position| position.line()),
+                    transaction: Err(err.to_string()),
+                    raw: RawRow(Raw::Record(ByteRecord::new())),
+                }));
+            }
+        };
+
+        let line = record.position().map_or(0, |position| position.line());
+        let (transaction, record) = match StringRecord::from_byte_record(record) {
+            Ok(text) => (
+                text.deserialize(Some(&self.headers))
+                    .map_err(|err| err.to_string()),
+                text.into_byte_record(),
+            ),
+            Err(err) => (
+                Err(format!(
+                    "invalid UTF-8 in field {}",
+                    err.utf8_error().field() + 1
+                )),
+                err.into_byte_record(),
+            ),
+        };
+        Some(Ok(DecodedRow {
+            line,
+            transaction,
+            raw: RawRow(Raw::Record(record)),
+        }))
+    }
+}
+
+/// Decodes NDJSON, with one transaction object per line using the
+/// same fields as CSV:
+/// ```text
+/// {"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"}
+/// {"type": "DePosit", "client": 1, "tx": 2, "amount": 1.0}
+/// {"type": "dispute", "client": 1, "tx": 1}
+/// ```
+/// Amounts may be strings or numbers, and numbers are read from
+/// their exact text so the four decimal limit still applies. Blank
+/// lines are skipped.
+pub struct NdjsonDecoder<R: Read> {
+    reader: BufReader<R>,
+    line: u64,
+}
+
+impl<R: Read> NdjsonDecoder<R> {
+    /// Create an NdjsonDecoder over a reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            line: 0,
+        }
+    }
+}
+
+impl<R: Read> Decoder for NdjsonDecoder<R> {
+    fn next_row(&mut self) -> Option<Result<DecodedRow>> {
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            match self.reader.read_line(&mut buffer) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+            if !buffer.trim().is_empty() {
+                break;
+            }
+        }
+
+        let raw = buffer.trim().to_string();
+        Some(Ok(DecodedRow {
+            line: self.line,
+            transaction: decode_json(&raw).map_err(|err| err.to_string()),
+            raw: RawRow(Raw::Line(raw)),
+        }))
+    }
+}
+
+/// Decodes a JSON object into a Transaction.
+fn decode_json(line: &str) -> serde_json::Result<Transaction> {
+    let fields: BTreeMap<String, &RawValue> = serde_json::from_str(line)?;
+
+    // Amounts deserialize from strings, so numeric amounts are taken as
+    // their exact text rather than going through a float.
+    let mut object = Map::new();
+    for (name, raw) in fields {
+        let text = raw.get();
+        let number = text.starts_with(|c: char| c == '-' || c.is_ascii_digit());
+        let value = match name.as_str() {
+            "amount" if number => Value::String(text.to_string()),
+            _ => serde_json::from_str(text)?,
+        };
+        object.insert(name, value);
+    }
+
+    serde_json::from_value(Value::Object(object))
+}
@@ -0,0 +1,258 @@
+use crate::amount::Amount;
+use std::fmt::{Display, Formatter};
+
+/// An EngineError describes why an Account or the Bank rejected
+/// a transaction. Each variant carries the identifiers and amounts
+/// involved so callers can branch on the kind of failure without
+/// matching on messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    /// The account is locked and can't be used.
+    AccountLocked { client: u16 },
+    /// The account is closed and can't be used again.
+    AccountClosed { client: u16 },
+    /// The account is not locked, so it can't be unlocked.
+    AccountNotLocked { client: u16 },
+    /// The account still has funds, so it can't be closed.
+    AccountNotEmpty { client: u16, total: Amount },
+    /// The account does not exist.
+    UnknownAccount { client: u16 },
+    /// The account does not have enough available funds.
+    InsufficientFunds {
+        client: u16,
+        requested: Amount,
+        available: Amount,
+    },
+    /// The account does not have enough held funds.
+    InsufficientHeldFunds {
+        client: u16,
+        requested: Amount,
+        held: Amount,
+    },
+    /// The account does not have enough authorized funds.
+    InsufficientAuthorizedFunds {
+        client: u16,
+        requested: Amount,
+        authorized: Amount,
+    },
+    /// A balance on the account would overflow.
+    Overflow { client: u16 },
+    /// A transaction with the same ID has already been logged.
+    DuplicateTransaction { tx: u32 },
+    /// The transaction requires an amount but did not specify one.
+    MissingAmount { tx: u32 },
+    /// The transaction's amount is not positive.
+    InvalidAmount { tx: u32, amount: Amount },
+    /// The transfer did not specify a destination client.
+    MissingDestination { tx: u32 },
+    /// The transfer's destination is the client making it.
+    SelfTransfer { tx: u32, client: u16 },
+    /// Admin operations are not allowed by the policy.
+    AdminDisabled { tx: u32 },
+    /// The admin operation did not specify who made it.
+    MissingActor { tx: u32 },
+    /// The admin operation did not specify why it was made.
+    MissingReason { tx: u32 },
+    /// The referenced transaction does not exist.
+    UnknownTransaction { tx: u32 },
+    /// The referenced transaction is not a deposit.
+    NotDeposit { tx: u32 },
+    /// The referenced transaction is not an authorization.
+    NotAuthorization { tx: u32 },
+    /// The referenced authorization was already captured, released or
+    /// expired.
+    HoldNotActive { tx: u32 },
+    /// The referenced authorization has no expiry, or hasn't reached it.
+    HoldNotExpired { tx: u32 },
+    /// The client does not match the client of the referenced transaction.
+    ClientMismatch { tx: u32, client: u16, expected: u16 },
+    /// The referenced transaction is already disputed.
+    AlreadyDisputed { tx: u32 },
+    /// The referenced transaction is not disputed.
+    NotDisputed { tx: u32 },
+    /// The referenced transaction was resolved and can't be disputed again.
+    AlreadyResolved { tx: u32 },
+    /// The referenced transaction was charged back and can't change.
+    AlreadyChargedBack { tx: u32 },
+    /// The dispute came more than the dispute window after the
+    /// referenced transaction.
+    DisputeWindowExpired { tx: u32, window: u64 },
+    /// The amount of a dispute, resolve or chargeback is not positive,
+    /// or is more than the portion of the transaction it can act on.
+    InvalidDisputeAmount {
+        tx: u32,
+        requested: Amount,
+        limit: Amount,
+    },
+    /// The transaction type is not implemented.
+    UnsupportedType { tx: u32, kind: String },
+    /// The transaction could not be read from or written to storage,
+    /// such as the write-ahead log or the transaction store, and was
+    /// undone.
+    Storage { tx: u32, message: String },
+}
+
+impl EngineError {
+    /// Returns a stable, machine-readable code for the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::AccountLocked { .. } => "account_locked",
+            Self::AccountClosed { .. } => "account_closed",
+            Self::AccountNotLocked { .. } => "account_not_locked",
+            Self::AccountNotEmpty { .. } => "account_not_empty",
+            Self::UnknownAccount { .. } => "unknown_account",
+            Self::InsufficientFunds { .. } => "insufficient_funds",
+            Self::InsufficientHeldFunds { .. } => "insufficient_held_funds",
+            Self::InsufficientAuthorizedFunds { .. } => "insufficient_authorized_funds",
+            Self::Overflow { .. } => "overflow",
+            Self::DuplicateTransaction { .. } => "duplicate_transaction",
+            Self::MissingAmount { .. } => "missing_amount",
+            Self::InvalidAmount { .. } => "invalid_amount",
+            Self::MissingDestination { .. } => "missing_destination",
+            Self::SelfTransfer { .. } => "self_transfer",
+            Self::AdminDisabled { .. } => "admin_disabled",
+            Self::MissingActor { .. } => "missing_actor",
+            Self::MissingReason { .. } => "missing_reason",
+            Self::UnknownTransaction { .. } => "unknown_transaction",
+            Self::NotDeposit { .. } => "not_deposit",
+            Self::NotAuthorization { .. } => "not_authorization",
+            Self::HoldNotActive { .. } => "hold_not_active",
+            Self::HoldNotExpired { .. } => "hold_not_expired",
+            Self::ClientMismatch { .. } => "client_mismatch",
+            Self::AlreadyDisputed { .. } => "already_disputed",
+            Self::NotDisputed { .. } => "not_disputed",
+            Self::AlreadyResolved { .. } => "already_resolved",
+            Self::AlreadyChargedBack { .. } => "already_charged_back",
+            Self::DisputeWindowExpired { .. } => "dispute_window_expired",
+            Self::InvalidDisputeAmount { .. } => "invalid_dispute_amount",
+            Self::UnsupportedType { .. } => "unsupported_type",
+            Self::Storage { .. } => "storage_error",
+        }
+    }
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AccountLocked { client } => write!(f, "Account {} is locked", client),
+            Self::AccountClosed { client } => write!(f, "Account {} is closed", client),
+            Self::AccountNotLocked { client } => write!(f, "Account {} is not locked", client),
+            Self::AccountNotEmpty { client, total } => {
+                write!(
+                    f,
+                    "Account {} still holds {} and can't be closed",
+                    client, total
+                )
+            }
+            Self::UnknownAccount { client } => write!(f, "Account {} does not exist", client),
+            Self::InsufficientFunds {
+                client,
+                requested,
+                available,
+            } => write!(
+                f,
+                "Insufficient funds in account {}: has {} wants {}",
+                client, available, requested
+            ),
+            Self::InsufficientHeldFunds {
+                client,
+                requested,
+                held,
+            } => write!(
+                f,
+                "Insufficient held funds in account {}: has {} wants {}",
+                client, held, requested
+            ),
+            Self::InsufficientAuthorizedFunds {
+                client,
+                requested,
+                authorized,
+            } => write!(
+                f,
+                "Insufficient authorized funds in account {}: has {} wants {}",
+                client, authorized, requested
+            ),
+            Self::Overflow { client } => write!(f, "Balance overflow in account {}", client),
+            Self::DuplicateTransaction { tx } => write!(f, "Transaction {} already exists", tx),
+            Self::MissingAmount { tx } => {
+                write!(f, "Transaction {} did not specify amount", tx)
+            }
+            Self::InvalidAmount { tx, amount } => {
+                write!(f, "Transaction {} amount {} must be positive", tx, amount)
+            }
+            Self::MissingDestination { tx } => {
+                write!(f, "Transfer {} did not specify destination", tx)
+            }
+            Self::SelfTransfer { tx, client } => {
+                write!(f, "Transfer {} is from account {} to itself", tx, client)
+            }
+            Self::AdminDisabled { tx } => {
+                write!(
+                    f,
+                    "Transaction {} is an admin operation, which are not allowed",
+                    tx
+                )
+            }
+            Self::MissingActor { tx } => {
+                write!(f, "Transaction {} did not specify actor", tx)
+            }
+            Self::MissingReason { tx } => {
+                write!(f, "Transaction {} did not specify reason", tx)
+            }
+            Self::UnknownTransaction { tx } => write!(f, "Invalid transaction reference {}", tx),
+            Self::NotDeposit { tx } => write!(f, "Transaction {} is not deposit", tx),
+            Self::NotAuthorization { tx } => {
+                write!(f, "Transaction {} is not authorization", tx)
+            }
+            Self::HoldNotActive { tx } => {
+                write!(f, "Authorization {} is no longer active", tx)
+            }
+            Self::HoldNotExpired { tx } => {
+                write!(f, "Authorization {} has not expired", tx)
+            }
+            Self::ClientMismatch {
+                tx,
+                client,
+                expected,
+            } => write!(
+                f,
+                "Client value {} did not match reference client {} for transaction {}",
+                client, expected, tx
+            ),
+            Self::AlreadyDisputed { tx } => write!(f, "Transaction {} is already disputed", tx),
+            Self::NotDisputed { tx } => write!(f, "Transaction {} is not disputed", tx),
+            Self::AlreadyResolved { tx } => {
+                write!(
+                    f,
+                    "Transaction {} was resolved and can't be disputed again",
+                    tx
+                )
+            }
+            Self::AlreadyChargedBack { tx } => {
+                write!(f, "Transaction {} was already charged back", tx)
+            }
+            Self::DisputeWindowExpired { tx, window } => write!(
+                f,
+                "Transaction {} can only be disputed within {} seconds",
+                tx, window
+            ),
+            Self::InvalidDisputeAmount {
+                tx,
+                requested,
+                limit,
+            } => write!(
+                f,
+                "Amount {} for transaction {} must be positive and at most {}",
+                requested, tx, limit
+            ),
+            Self::UnsupportedType { tx, kind } => {
+                write!(f, "Transaction {} type '{}' not implemented", tx, kind)
+            }
+            Self::Storage { tx, message } => {
+                write!(f, "Storage failed for transaction {}: {}", tx, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
@@ -0,0 +1,165 @@
+use crate::amount::Amount;
+use crate::error::EngineError;
+use anyhow::{Context, Error, Result};
+use csv::Trim;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// The raw count of ten-thousandths in 100%, as percentages are
+/// Amounts such as `1.5` for 1.5%.
+const HUNDRED_PERCENT: i128 = 100 * 10_000;
+
+/// A FeeKind is how a fee rule's value is charged.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeKind {
+    /// The value is charged as is.
+    Flat,
+    /// The value is a percentage of the transaction's amount.
+    Percent,
+}
+
+/// A FeeRule charges a fee on transactions of a type, for every client
+/// or only for one. Rules with `up_to` are tiers that only apply to
+/// amounts up to and including it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FeeRule {
+    pub r#type: String,
+    #[serde(default)]
+    pub client: Option<u16>,
+    pub fee: FeeKind,
+    pub value: Amount,
+    #[serde(default)]
+    pub up_to: Option<Amount>,
+}
+
+/// A FeeSchedule decides the fee a transaction is charged, read from
+/// a CSV file of rules with a header row:
+/// ```text
+/// type, client, fee, value, up_to
+/// withdrawal, , flat, 0.5
+/// withdrawal, 7, percent, 1.0
+/// transfer, , percent, 1.0, 100.0
+/// transfer, , flat, 2.0
+/// chargeback, , flat, 15.0
+/// ```
+/// Rules for a client replace the rules for every client, so above
+/// client 7 pays 1% on withdrawals and everyone else 0.5. A type with
+/// several rules is tiered: the tier with the smallest `up_to` that
+/// covers the amount applies, or else the rule without one, so above
+/// transfers up to 100.0 cost 1% and larger ones 2.0.
+///
+/// Percentages are of the transaction's amount, or of the portion of
+/// the transaction it refers to that it acts on if it has none, rounded
+/// to the nearest ten-thousandth.
+#[derive(Clone, Debug, Default)]
+pub struct FeeSchedule {
+    rules: Vec<FeeRule>,
+}
+
+impl FeeSchedule {
+    /// Create a FeeSchedule from a set of rules.
+    pub fn new(rules: Vec<FeeRule>) -> Self {
+        let rules = rules
+            .into_iter()
+            .map(|rule| FeeRule {
+                r#type: rule.r#type.to_lowercase(),
+                ..rule
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Loads a FeeSchedule from a CSV file of rules.
+    ///
+    /// Returns an Err if the file can't be read or has an invalid rule.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        Self::read(file).with_context(|| format!("Invalid fee schedule {}", path.display()))
+    }
+
+    /// Reads a FeeSchedule from CSV rules.
+    ///
+    /// Returns an Err if the reader fails or a rule is invalid, such as
+    /// a negative value.
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .from_reader(reader);
+
+        let mut rules = vec![];
+        for rule in reader.deserialize() {
+            let rule: FeeRule = rule?;
+            if rule.value.is_negative() {
+                return Err(Error::msg(format!(
+                    "Fee for type '{}' is negative",
+                    rule.r#type
+                )));
+            }
+            rules.push(rule);
+        }
+        Ok(Self::new(rules))
+    }
+
+    /// Returns the fee a client is charged for a transaction of a type
+    /// and amount, or None if there is no fee.
+    ///
+    /// Returns an Err if the fee does not fit in an Amount.
+    pub fn fee(
+        &self,
+        kind: &str,
+        client: u16,
+        amount: Amount,
+    ) -> Result<Option<Amount>, EngineError> {
+        let rules = || self.rules.iter().filter(move |rule| rule.r#type == kind);
+        let client_rules = rules().any(|rule| rule.client == Some(client));
+        let candidates =
+            rules().filter(|rule| rule.client == if client_rules { Some(client) } else { None });
+
+        // The smallest tier covering the amount, or the catch-all rule.
+        let rule = candidates
+            .clone()
+            .filter(|rule| rule.up_to.is_some_and(|up_to| amount <= up_to))
+            .min_by_key(|rule| rule.up_to)
+            .or_else(|| candidates.clone().find(|rule| rule.up_to.is_none()));
+        let Some(rule) = rule else {
+            return Ok(None);
+        };
+
+        let fee = match rule.fee {
+            FeeKind::Flat => rule.value,
+            FeeKind::Percent => {
+                let raw = i128::from(amount.raw()) * i128::from(rule.value.raw());
+                // Round half away from zero.
+                let half = HUNDRED_PERCENT / 2 * raw.signum();
+                i64::try_from((raw + half) / HUNDRED_PERCENT)
+                    .map(Amount::from_raw)
+                    .map_err(|_| EngineError::Overflow { client })?
+            }
+        };
+        Ok((fee > Amount::default()).then_some(fee))
+    }
+}
+
+/// A FeeEntry is a fee charged to a client for a transaction, as it is
+/// kept in the fee ledger. A fee that is reversed, which happens when
+/// its transaction is charged back rather than when it is disputed,
+/// gets a second entry crediting it back, so the ledger is only ever
+/// appended to:
+/// ```text
+/// {"tx":2,"client":1,"type":"withdrawal","amount":"0.5000"}
+/// {"tx":2,"client":1,"type":"withdrawal","amount":"0.5000","reversal":true}
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FeeEntry {
+    pub tx: u32,
+    pub client: u16,
+    pub r#type: String,
+    pub amount: Amount,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reversal: bool,
+}
@@ -0,0 +1,180 @@
+use crate::account::Account;
+use crate::amount::Amount;
+use crate::error::EngineError;
+use crate::report::OutputFormat;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// A HistoryEvent is a transaction handled for a client, accepted or
+/// not, along with the client's balances once it was handled:
+/// ```text
+/// {"tx":1,"client":1,"type":"deposit","amount":"1.5000","available":"1.5000","held":"0.0000"}
+/// {"tx":2,"client":1,"type":"withdrawal","amount":"5.0000","rejected":"insufficient_funds",
+///  "available":"1.5000","held":"0.0000"}
+/// ```
+/// A transfer, and a dispute, resolve or chargeback of one, is in the
+/// history of both clients.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEvent {
+    pub tx: u32,
+    pub client: u16,
+    pub r#type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<Amount>,
+    /// Why the transaction was rejected, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<String>,
+    pub available: Amount,
+    pub held: Amount,
+    #[serde(default, skip_serializing_if = "Amount::is_zero")]
+    pub authorized: Amount,
+}
+
+impl HistoryEvent {
+    /// Create an event of a transaction for a client with the balances
+    /// of their account, or none if they have no account.
+    pub fn new(
+        transaction: &Transaction,
+        client: u16,
+        account: Option<&Account>,
+        rejected: Option<&EngineError>,
+    ) -> Self {
+        Self {
+            tx: transaction.tx,
+            client,
+            r#type: transaction.get_type(),
+            amount: transaction.amount,
+            rejected: rejected.map(|err| err.code().to_string()),
+            available: account.map_or_else(Amount::default, |account| account.available),
+            held: account.map_or_else(Amount::default, |account| account.held),
+            authorized: account.map_or_else(Amount::default, |account| account.authorized),
+        }
+    }
+
+    /// Calculates the client's total balance once the transaction was
+    /// handled, which includes held and authorized funds.
+    ///
+    /// Returns an Err if the total does not fit in an Amount.
+    pub fn total(&self) -> Result<Amount, EngineError> {
+        self.available
+            .checked_add(self.held)
+            .and_then(|total| total.checked_add(self.authorized))
+            .ok_or(EngineError::Overflow {
+                client: self.client,
+            })
+    }
+
+    /// Returns `accepted`, or why the transaction was rejected.
+    pub fn status(&self) -> &str {
+        self.rejected.as_deref().unwrap_or("accepted")
+    }
+}
+
+/// A row of a Statement as it is serialized.
+#[derive(Serialize, Debug)]
+struct StatementRow<'a> {
+    tx: u32,
+    r#type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount: Option<Amount>,
+    status: &'a str,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+}
+
+impl<'a> TryFrom<&'a HistoryEvent> for StatementRow<'a> {
+    type Error = EngineError;
+
+    fn try_from(event: &'a HistoryEvent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            tx: event.tx,
+            r#type: &event.r#type,
+            amount: event.amount,
+            status: event.status(),
+            available: event.available,
+            held: event.held,
+            total: event.total()?,
+        })
+    }
+}
+
+/// A Statement displays the history of a client in the order it
+/// happened, with the running balances after each transaction, in a
+/// CSV format by default:
+/// ```text
+/// tx, type, amount, status, available, held, total
+/// 1, deposit, 1.5000, accepted, 1.5000, 0.0000, 1.5000
+/// 2, withdrawal, 5.0000, insufficient_funds, 1.5000, 0.0000, 1.5000
+/// 1, dispute, , accepted, 0.0000, 1.5000, 1.5000
+/// ```
+/// Or in a JSON format, either as one array or one row per line:
+/// ```text
+/// {"tx":1,"type":"deposit","amount":"1.5000","status":"accepted","available":"1.5000",
+///  "held":"0.0000","total":"1.5000"}
+/// ```
+pub struct Statement<'a> {
+    events: &'a [HistoryEvent],
+    format: OutputFormat,
+}
+
+impl<'a> Statement<'a> {
+    /// Create a CSV statement of a client's history.
+    pub fn new(events: &'a [HistoryEvent]) -> Self {
+        Self {
+            events,
+            format: OutputFormat::Csv,
+        }
+    }
+
+    /// Sets the format the statement is displayed in.
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Display for Statement<'_> {
+    /// Note that a client without history will only print a header in
+    /// CSV, an empty array in JSON, and nothing in NDJSON.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let rows = self
+            .events
+            .iter()
+            .map(StatementRow::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| std::fmt::Error)?;
+
+        match self.format {
+            OutputFormat::Csv => {
+                writeln!(f, "tx, type, amount, status, available, held, total")?;
+                for row in rows {
+                    let amount = row.amount.map(|amount| amount.to_string());
+                    writeln!(
+                        f,
+                        "{}, {}, {}, {}, {}, {}, {}",
+                        row.tx,
+                        row.r#type,
+                        amount.unwrap_or_default(),
+                        row.status,
+                        row.available,
+                        row.held,
+                        row.total
+                    )?;
+                }
+            }
+            OutputFormat::Json => {
+                let json = serde_json::to_string(&rows).map_err(|_| std::fmt::Error)?;
+                writeln!(f, "{}", json)?;
+            }
+            OutputFormat::Ndjson => {
+                for row in rows {
+                    let json = serde_json::to_string(&row).map_err(|_| std::fmt::Error)?;
+                    writeln!(f, "{}", json)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
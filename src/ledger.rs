@@ -0,0 +1,180 @@
+use crate::account::Account;
+use crate::amount::Amount;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A LedgerAccount is somewhere funds can be in the ledger. Each client
+/// has its available, held and authorized funds, and the rest are kept
+/// by the bank:
+/// ```text
+/// cash         funds deposited from and withdrawn to outside the bank
+/// chargebacks  funds taken back by chargebacks, or paid out by them
+/// fees         fees charged to clients
+/// opening      balances restored from a snapshot without a ledger
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerAccount {
+    Available(u16),
+    Held(u16),
+    Authorized(u16),
+    Cash,
+    Chargebacks,
+    Fees,
+    Opening,
+}
+
+/// A Posting moves an amount from one LedgerAccount to another for a
+/// transaction, so every posting balances by itself:
+/// ```text
+/// {"tx":1,"from":"cash","to":{"available":1},"amount":"1.5000"}
+/// {"tx":1,"from":{"available":1},"to":{"held":1},"amount":"1.5000"}
+/// ```
+/// Postings of balances restored from a snapshot without a ledger have
+/// no tx.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Posting {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx: Option<u32>,
+    pub from: LedgerAccount,
+    pub to: LedgerAccount,
+    pub amount: Amount,
+}
+
+/// A Ledger is the journal of every movement of funds, in the order
+/// they happened. Postings are recorded alongside the changes to the
+/// client accounts, which keep their own balances, and `verify` checks
+/// that the two agree.
+#[derive(Clone, Debug, Default)]
+pub struct Ledger {
+    postings: Vec<Posting>,
+}
+
+impl Ledger {
+    /// Records an amount moving from one LedgerAccount to another.
+    /// Nothing is recorded for a zero amount.
+    pub fn post(
+        &mut self,
+        tx: Option<u32>,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        amount: Amount,
+    ) {
+        if !amount.is_zero() {
+            self.postings.push(Posting {
+                tx,
+                from,
+                to,
+                amount,
+            });
+        }
+    }
+
+    /// Returns the postings, in the order they were made.
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    /// Returns the number of postings.
+    pub fn len(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Returns whether there are no postings.
+    pub fn is_empty(&self) -> bool {
+        self.postings.is_empty()
+    }
+
+    /// Removes the postings made after the first `len`.
+    pub fn truncate(&mut self, len: usize) {
+        self.postings.truncate(len);
+    }
+
+    /// Appends the postings of another ledger.
+    pub fn extend(&mut self, other: Ledger) {
+        self.postings.extend(other.postings);
+    }
+
+    /// Replaces the postings.
+    pub fn replace(&mut self, postings: Vec<Posting>) {
+        self.postings = postings;
+    }
+
+    /// Works out the balance of every LedgerAccount from the postings.
+    ///
+    /// Returns an Err if a balance does not fit in an Amount.
+    pub fn balances(&self) -> Result<BTreeMap<LedgerAccount, Amount>> {
+        let mut balances = BTreeMap::new();
+        for posting in &self.postings {
+            let from = balances.entry(posting.from).or_insert_with(Amount::default);
+            *from = from
+                .checked_sub(posting.amount)
+                .ok_or_else(|| overflow(posting.from))?;
+            let to = balances.entry(posting.to).or_insert_with(Amount::default);
+            *to = to
+                .checked_add(posting.amount)
+                .ok_or_else(|| overflow(posting.to))?;
+        }
+        Ok(balances)
+    }
+
+    /// Returns the balance of a LedgerAccount worked out from the
+    /// postings.
+    ///
+    /// Returns an Err if the balance does not fit in an Amount.
+    pub fn balance(&self, account: LedgerAccount) -> Result<Amount> {
+        Ok(self.balances()?.get(&account).copied().unwrap_or_default())
+    }
+
+    /// Checks that every client account holds exactly the funds the
+    /// postings put in it, and that the postings put no funds in
+    /// accounts that don't exist. Every posting moves an amount out of
+    /// one LedgerAccount and into another, so money is conserved by the
+    /// postings themselves.
+    ///
+    /// Returns an Err describing the first difference found.
+    pub fn verify<'a, I>(&self, accounts: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a Account>,
+    {
+        let mut balances = self.balances()?;
+
+        for account in accounts {
+            let client = account.client();
+            for (ledger_account, held) in [
+                (LedgerAccount::Available(client), account.available),
+                (LedgerAccount::Held(client), account.held),
+                (LedgerAccount::Authorized(client), account.authorized),
+            ] {
+                let posted = balances.remove(&ledger_account).unwrap_or_default();
+                if posted != held {
+                    return Err(Error::msg(format!(
+                        "Account {} holds {} in {:?} but the ledger has {}",
+                        client, held, ledger_account, posted
+                    )));
+                }
+            }
+        }
+
+        // Whatever is left over belongs to the bank, or to no account.
+        for (ledger_account, posted) in balances {
+            let client = matches!(
+                ledger_account,
+                LedgerAccount::Available(_) | LedgerAccount::Held(_) | LedgerAccount::Authorized(_)
+            );
+            if client && !posted.is_zero() {
+                return Err(Error::msg(format!(
+                    "The ledger has {} in {:?} for an account that does not exist",
+                    posted, ledger_account
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns an error for a LedgerAccount whose balance overflowed.
+fn overflow(account: LedgerAccount) -> Error {
+    Error::msg(format!("The ledger balance of {:?} overflows", account))
+}
@@ -0,0 +1,36 @@
+//! PayEngine processes transactions against client accounts.
+//!
+//! A [`Bank`] holds the accounts and the log of past transactions.
+//! Transactions can be fed to it one at a time through
+//! [`Bank::handle_transaction`], or read in bulk from a CSV file
+//! with [`Bank::process_transactions`]. Other input formats are
+//! supported through [`decoder::Decoder`].
+
+pub mod account;
+pub mod amount;
+pub mod audit;
+pub mod bank;
+pub mod decoder;
+pub mod error;
+pub mod fees;
+pub mod history;
+pub mod ledger;
+pub mod parallel;
+pub mod policy;
+pub mod query;
+pub mod rejects;
+pub mod report;
+pub mod server;
+pub mod snapshot;
+pub mod store;
+#[cfg(test)]
+mod test;
+pub mod transaction;
+pub mod wal;
+
+pub use account::{Account, AccountStatus};
+pub use amount::Amount;
+pub use bank::Bank;
+pub use error::EngineError;
+pub use policy::Policy;
+pub use transaction::{DisputeState, Hold, HoldState, LoggedTransaction, OutOfOrder, Transaction};
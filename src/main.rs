@@ -1,26 +1,189 @@
 use anyhow::{Context, Result};
-use bank::Bank;
+use cli::{Command, Options};
+use payengine::decoder::{Decoder, InputFormat};
+use payengine::fees::FeeSchedule;
+use payengine::parallel::ShardedBank;
+use payengine::query::Cutoff;
+use payengine::rejects::{RejectsFormat, RejectsWriter};
+use payengine::server::Server;
+use payengine::store::{DiskTransactionStore, TransactionStore};
+use payengine::transaction::unix_now;
+use payengine::wal::Wal;
+use payengine::Bank;
 use std::env::args;
+use std::fs::File;
+use std::io::stdin;
+use std::path::Path;
 
-mod account;
-mod bank;
-#[cfg(test)]
-mod test;
-mod transaction;
+mod cli;
 
 fn main() -> Result<()> {
-    // Grab the file path from the 1st argument
-    let transaction_file = args()
-        .nth(1)
-        .context("No file specified as first argument. Please specify a file.")?;
-
-    // Create a bank to run the transactions through.
-    let mut bank = Bank::new();
-    bank.process_transactions(transaction_file)?;
-
-    // Display the bank, printing a CSV format with header
-    // of all available accounts after transactions.
-    println!("{}", bank);
+    // Grab the file paths and options from the arguments.
+    let options = Options::parse(args().skip(1))?;
+
+    // Create a bank to run the transactions through, logging them on
+    // disk or spreading them over threads if asked to.
+    match &options.tx_store {
+        Some(path) => {
+            let store = DiskTransactionStore::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let mut bank = Bank::with_store(store, options.policy.clone());
+            prepare(&mut bank, &options)?;
+            process_inputs(&options, |source, decoder| {
+                bank.process_decoder(source, decoder)
+            })?;
+            finish(bank, &options)
+        }
+        None if options.threads > 1 => {
+            let mut bank = Bank::with_policy(options.policy.clone());
+            prepare(&mut bank, &options)?;
+            let mut sharded = ShardedBank::new(bank, options.threads)?;
+            process_inputs(&options, |source, decoder| {
+                sharded.process_decoder(source, decoder)
+            })?;
+            finish(sharded.into_bank()?, &options)
+        }
+        None => {
+            let mut bank = Bank::with_policy(options.policy.clone());
+            prepare(&mut bank, &options)?;
+            process_inputs(&options, |source, decoder| {
+                bank.process_decoder(source, decoder)
+            })?;
+            finish(bank, &options)
+        }
+    }
+}
+
+/// Sets a bank up to charge fees, keep a ledger and histories, continue
+/// from a previous run and report rejections.
+fn prepare<T: TransactionStore>(bank: &mut Bank<T>, options: &Options) -> Result<()> {
+    // Charge fees before replaying, so replayed transactions pay them too.
+    if let Some(path) = &options.fees {
+        let fees = FeeSchedule::load(path)
+            .with_context(|| format!("Failed to load fees from {}", path.display()))?;
+        bank.set_fees(fees);
+    }
+
+    // Keep a ledger and histories before loading, so the state's are
+    // loaded too.
+    if options.verify_ledger {
+        bank.keep_ledger();
+    }
+    if options.keep_history {
+        bank.keep_history();
+    }
+
+    // Continue from a previous run's state if one was given.
+    if let Some(path) = &options.state_in {
+        bank.load_snapshot(path)
+            .with_context(|| format!("Failed to load state from {}", path.display()))?;
+    }
+
+    // Replay what a crashed run logged since that state, and keep logging.
+    if let Some(path) = &options.wal {
+        bank.replay_wal(path)
+            .with_context(|| format!("Failed to replay {}", path.display()))?;
+        bank.set_wal(Wal::open(path, options.wal_sync_every)?);
+    }
+
+    // Expire the holds that ran out since the state was saved.
+    bank.expire_holds(unix_now())?;
+
+    // Optionally write rejected rows out, guessing the format from
+    // the file extension if it wasn't specified.
+    if let Some(path) = &options.rejects {
+        let format = options
+            .rejects_format
+            .unwrap_or_else(|| RejectsFormat::from_path(path));
+        bank.set_rejects(RejectsWriter::create(path, format)?);
+    }
+    Ok(())
+}
+
+/// Decodes every input in order, reading stdin for `-`, and hands each
+/// decoder to `process` along with the name of the input. Inputs end
+/// early at the `--as-of` point if there is one.
+///
+/// Returns an Err if an input fails, or the `--as-of` point is not in
+/// the inputs.
+fn process_inputs<F>(options: &Options, mut process: F) -> Result<()>
+where
+    F: FnMut(&str, Box<dyn Decoder + '_>) -> Result<()>,
+{
+    let mut cutoff = options.as_of.clone().map(Cutoff::new);
+    for input in &options.inputs {
+        if cutoff.as_ref().is_some_and(Cutoff::reached) {
+            break;
+        }
+
+        let (name, decoder) = if input == Path::new("-") {
+            let format = options.input_format.unwrap_or_default();
+            ("-".to_string(), format.decoder(stdin().lock())?)
+        } else {
+            // Guess the format from the file extension if it wasn't specified.
+            let format = options
+                .input_format
+                .unwrap_or_else(|| InputFormat::from_path(input));
+            let file =
+                File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+            (input.display().to_string(), format.decoder(file)?)
+        };
+        let decoder = match cutoff.as_mut() {
+            Some(cutoff) => Box::new(cutoff.decoder(&name, decoder)),
+            None => decoder,
+        };
+        process(&name, decoder).with_context(|| format!("Failed to process {}", name))?;
+    }
+
+    cutoff.as_ref().map_or(Ok(()), Cutoff::check)
+}
+
+/// Expires holds and verifies the ledger if asked to, then saves the
+/// state of a bank for the next run and prints the report or a client's
+/// statement or balances, or serves the bank if asked to.
+fn finish<T: TransactionStore + Send + 'static>(
+    mut bank: Bank<T>,
+    options: &Options,
+) -> Result<()> {
+    // Expire the holds that ran out while processing, unless looking
+    // at the state as of a point in the inputs.
+    if options.as_of.is_none() {
+        bank.expire_holds(unix_now())?;
+    }
+
+    // Prove the balances add up before anything relies on them.
+    if options.verify_ledger {
+        bank.verify_ledger().context("Ledger verification failed")?;
+    }
+
+    if let Some(address) = &options.serve {
+        let server = Server::bind(address.as_str(), bank)
+            .with_context(|| format!("Failed to listen on {}", address))?;
+        eprintln!("Listening on {}", server.local_addr()?);
+        return server.run();
+    }
+
+    // Save the state for the next run before reporting on it.
+    if let Some(path) = &options.state_out {
+        bank.save_snapshot(path)
+            .with_context(|| format!("Failed to save state to {}", path.display()))?;
+    }
+
+    match options.command {
+        Command::Statement(client) => {
+            print!("{}", bank.statement(client).format(options.output_format));
+        }
+        Command::Balance(client) => {
+            let report = bank.report(options.sort, options.order).only(client);
+            print!("{}", report.format(options.output_format));
+        }
+        Command::Report => {
+            // Display the bank, printing a CSV format with header (or JSON)
+            // of all available accounts after transactions.
+            let report = bank.report(options.sort, options.order);
+            print!("{}", report.format(options.output_format));
+        }
+    }
 
     Ok(())
 }
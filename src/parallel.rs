@@ -0,0 +1,478 @@
+use crate::account::Account;
+use crate::bank::Bank;
+use crate::decoder::{DecodedRow, Decoder, InputFormat, RawRow};
+use crate::error::EngineError;
+use crate::history::HistoryEvent;
+use crate::rejects::PARSE_ERROR;
+use crate::transaction::{LoggedTransaction, Transaction};
+use anyhow::{Error, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// The most shards a ShardedBank can run.
+pub const MAX_SHARDS: usize = 64;
+
+/// The number of transactions that can be queued for a shard before
+/// reading the input waits for it to catch up.
+const QUEUE_LEN: usize = 1024;
+
+/// A ShardedBank processes transactions on a number of threads, each
+/// owning the accounts of the clients whose ID maps to it. A client's
+/// transactions all go to the same shard in input order, so each
+/// account sees the same transactions in the same order as it would
+/// in a single Bank, and the merged result is identical.
+///
+/// Transaction IDs are shared between clients though, so a deposit can
+/// be a duplicate of another client's deposit, and a dispute can name
+/// another client's transaction. The tx IDs each shard has seen logged
+/// transactions (deposits, withdrawals, transfers and authorizations)
+/// for are tracked, and when a transaction uses a tx ID another shard
+/// has seen, that shard is asked whether it logged it before going on.
+/// This waits for the shard to catch up, so inputs that do this often
+/// process closer to sequentially.
+///
+/// A transfer touches the accounts of two clients, which can be on
+/// different shards. The destination account is then moved over to the
+/// client's shard for the transfer, and back once it's handled, as it
+/// is for disputes of the transfer later on. This also waits for both
+/// shards to catch up.
+///
+/// Rejected rows are reported in input order at the end of each input,
+/// rather than as they happen. Admin actions are applied by the shards,
+/// and are put back in the order they were read when the audit logs are
+/// merged.
+pub struct ShardedBank {
+    /// Holds the policy and rejects sink, and gets the merged state back.
+    front: Bank,
+    shards: Vec<Shard>,
+    /// The shards that have seen a logged transaction (deposit,
+    /// withdrawal, transfer or authorization) with a tx ID, as a bit per
+    /// shard.
+    claims: HashMap<u32, u64>,
+    /// The destinations of transfers to a client on another shard, by
+    /// tx ID. A tx ID can be used by more than one transfer attempt.
+    transfers: HashMap<u32, Vec<u16>>,
+    /// The number of transactions sent to the shards so far, which
+    /// numbers each one in the order it was read.
+    sent: u64,
+    /// Rejections from every shard.
+    rejected: Receiver<Rejected>,
+}
+
+/// A thread running a Bank for a subset of clients.
+struct Shard {
+    sender: SyncSender<Message>,
+    /// Gives back the bank, along with the number of the transaction
+    /// that applied each entry of its audit log.
+    handle: JoinHandle<(Bank, Vec<u64>)>,
+}
+
+/// A request to a shard.
+enum Message {
+    /// Handle a transaction read from a line of the input, as of the
+    /// latest timestamp the front has seen. `sequence` numbers it among
+    /// every transaction sent to the shards.
+    Transaction {
+        line: u64,
+        sequence: u64,
+        transaction: Transaction,
+        raw: RawRow,
+        latest: Option<u64>,
+    },
+    /// Record a transaction rejected before it reached the shard in the
+    /// client's history.
+    Rejected {
+        transaction: Transaction,
+        err: EngineError,
+    },
+    /// Look up a logged transaction.
+    Lookup {
+        tx: u32,
+        reply: Sender<Option<LoggedTransaction>>,
+    },
+    /// Remove a client's account and history, replying with them.
+    Take {
+        client: u16,
+        reply: Sender<(Option<Account>, Vec<HistoryEvent>)>,
+    },
+    /// Insert a client's account and history taken from another shard.
+    Give {
+        client: u16,
+        account: Option<Account>,
+        history: Vec<HistoryEvent>,
+    },
+    /// Reply once every earlier request is handled.
+    Flush { reply: Sender<()> },
+}
+
+/// A rejected row, waiting to be reported in input order.
+struct Rejected {
+    line: u64,
+    record: String,
+    reason: &'static str,
+    message: String,
+}
+
+impl ShardedBank {
+    /// Create a ShardedBank that runs a number of shards, continuing from
+    /// the state of a bank. The bank's policy is used by every shard, and
+    /// its rejects sink gets every rejected row.
+    ///
+    /// Returns an Err if the number of shards is 0 or over MAX_SHARDS.
+    pub fn new(mut bank: Bank, shards: usize) -> Result<Self> {
+        if shards == 0 || shards > MAX_SHARDS {
+            return Err(Error::msg(format!(
+                "Shards must be between 1 and {}, got {}",
+                MAX_SHARDS, shards
+            )));
+        }
+
+        let (rejections, rejected) = channel();
+        let mut claims: HashMap<u32, u64> = HashMap::new();
+        let mut transfers: HashMap<u32, Vec<u16>> = HashMap::new();
+        let count = shards;
+        let shards = bank
+            .split(shards)
+            .into_iter()
+            .enumerate()
+            .map(|(index, shard)| {
+                for (tx, logged) in shard.logged_transactions() {
+                    *claims.entry(tx).or_default() |= 1 << index;
+                    if let Some(destination) = logged.destination {
+                        if usize::from(destination) % count != index {
+                            transfers.entry(tx).or_default().push(destination);
+                        }
+                    }
+                }
+                Shard::spawn(shard, rejections.clone())
+            })
+            .collect();
+
+        Ok(Self {
+            front: bank,
+            shards,
+            claims,
+            transfers,
+            sent: 0,
+            rejected,
+        })
+    }
+
+    /// Attempts to parse the passed transaction_path as a CSV or NDJSON
+    /// file, guessed from its extension, processing it across the shards.
+    /// See `Bank::process_transactions`.
+    ///
+    /// This function returns an Err if the file fails to open.
+    pub fn process_transactions<P: AsRef<Path>>(&mut self, transaction_path: P) -> Result<()> {
+        let transaction_path = transaction_path.as_ref();
+
+        let file = File::options().read(true).open(transaction_path)?;
+        let decoder = InputFormat::from_path(transaction_path).decoder(file)?;
+        self.process_decoder(&transaction_path.display().to_string(), decoder)
+    }
+
+    /// Decodes Transactions from the passed decoder, processing them
+    /// across the shards. See `Bank::process_decoder`.
+    ///
+    /// This function returns an Err if the input can't be read, a shard
+    /// has stopped, or writing to the rejects sink fails.
+    pub fn process_decoder<D: Decoder>(&mut self, source: &str, mut decoder: D) -> Result<()> {
+        let mut rejected = vec![];
+
+        while let Some(row) = decoder.next_row() {
+            let DecodedRow {
+                line,
+                transaction,
+                raw,
+            } = row?;
+
+            let transaction = match transaction {
+                Ok(transaction) => transaction,
+                Err(message) => {
+                    rejected.push(Rejected {
+                        line,
+                        record: raw.text(),
+                        reason: PARSE_ERROR,
+                        message,
+                    });
+                    continue;
+                }
+            };
+
+            // The front sees every row in order, so it checks the order.
+            if let Some(flag) = self.front.check_order(&transaction) {
+                eprintln!("{}:{}: {}", source, line, flag);
+            }
+
+            match self.route(&transaction)? {
+                Ok(shard) => self.send(shard, line, transaction, raw)?,
+                Err(err) => {
+                    rejected.push(Rejected {
+                        line,
+                        record: raw.text(),
+                        reason: err.code(),
+                        message: err.to_string(),
+                    });
+                    let shard = self.shard_of(transaction.client);
+                    self.shards[shard].send(Message::Rejected { transaction, err })?;
+                }
+            }
+        }
+
+        // Wait for every shard to finish the input, then report what was
+        // rejected in input order.
+        for shard in &self.shards {
+            let (reply, done) = channel();
+            shard.send(Message::Flush { reply })?;
+            done.recv().map_err(|_| stopped())?;
+        }
+        rejected.extend(self.rejected.try_iter());
+        rejected.sort_by_key(|rejected| rejected.line);
+
+        for Rejected {
+            line,
+            record,
+            reason,
+            message,
+        } in rejected
+        {
+            self.front.reject(source, line, &record, reason, message)?;
+        }
+        self.front.flush()
+    }
+
+    /// Stops the shards and merges their state into a single Bank.
+    ///
+    /// Returns an Err if a shard has stopped unexpectedly.
+    pub fn into_bank(self) -> Result<Bank> {
+        let mut bank = self.front;
+        let mut audit = vec![];
+        for shard in self.shards {
+            // Dropping the sender lets the shard finish.
+            drop(shard.sender);
+            let (mut shard, sequences) = shard.handle.join().map_err(|_| stopped())?;
+            audit.extend(sequences.into_iter().zip(shard.take_audit()));
+            bank.absorb(shard);
+        }
+
+        // The audit log is in the order actions were applied, which is
+        // the order they were read across every shard.
+        audit.sort_by_key(|(sequence, _)| *sequence);
+        bank.extend_audit(audit.into_iter().map(|(_, entry)| entry));
+        Ok(bank)
+    }
+
+    /// Returns the shard a client's account lives on.
+    fn shard_of(&self, client: u16) -> usize {
+        usize::from(client) % self.shards.len()
+    }
+
+    /// Sends a transaction to its shard, along with the accounts on other
+    /// shards it may touch, which are moved back once it's handled.
+    ///
+    /// Returns an Err if a shard has stopped.
+    fn send(
+        &mut self,
+        shard: usize,
+        line: u64,
+        transaction: Transaction,
+        raw: RawRow,
+    ) -> Result<()> {
+        // Transfers touch their destination, as do disputes of them.
+        let mut borrowed: Vec<u16> = transaction
+            .destination
+            .filter(|_| transaction.get_type() == "transfer")
+            .into_iter()
+            .chain(
+                self.transfers
+                    .get(&transaction.tx)
+                    .into_iter()
+                    .flatten()
+                    .copied(),
+            )
+            .filter(|&client| self.shard_of(client) != shard)
+            .collect();
+        borrowed.sort_unstable();
+        borrowed.dedup();
+
+        for &client in &borrowed {
+            let (account, history) = self.shards[self.shard_of(client)].take(client)?;
+            self.shards[shard].send(Message::Give {
+                client,
+                account,
+                history,
+            })?;
+        }
+        self.sent += 1;
+        self.shards[shard].send(Message::Transaction {
+            line,
+            sequence: self.sent,
+            transaction,
+            raw,
+            latest: self.front.latest_timestamp(),
+        })?;
+        for &client in &borrowed {
+            let (account, history) = self.shards[shard].take(client)?;
+            self.shards[self.shard_of(client)].send(Message::Give {
+                client,
+                account,
+                history,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Picks the shard for a transaction, or rejects it if it clashes
+    /// with a transaction logged by another shard the same way a single
+    /// Bank would.
+    ///
+    /// Returns an Err if a shard has stopped.
+    fn route(&mut self, transaction: &Transaction) -> Result<Result<usize, EngineError>> {
+        let shard = self.shard_of(transaction.client);
+        let tx = transaction.tx;
+
+        let kind = transaction.get_type();
+        let logs = matches!(
+            kind.as_str(),
+            "deposit" | "withdrawal" | "transfer" | "authorize"
+        );
+        let holds = matches!(kind.as_str(), "capture" | "release" | "expire");
+        let references = holds || matches!(kind.as_str(), "dispute" | "resolve" | "chargeback");
+        if !logs && !references {
+            return Ok(Ok(shard));
+        }
+
+        // Only shards that have seen a deposit or withdrawal with this
+        // tx ID can have logged it, and at most one of them has.
+        let others = self.claims.get(&tx).copied().unwrap_or(0) & !(1 << shard);
+        for other in (0..self.shards.len()).filter(|other| others & (1 << other) != 0) {
+            if let Some(logged) = self.shards[other].lookup(tx)? {
+                if logs {
+                    return Ok(Err(EngineError::DuplicateTransaction { tx }));
+                }
+                let checked = if holds {
+                    self.front.check_authorization(tx, &logged)
+                } else {
+                    self.front.check_disputable(tx, &logged)
+                };
+                return Ok(Err(match checked {
+                    Err(err) => err,
+                    Ok(()) => EngineError::ClientMismatch {
+                        tx,
+                        client: transaction.client,
+                        expected: logged.client,
+                    },
+                }));
+            }
+        }
+
+        if logs {
+            *self.claims.entry(tx).or_default() |= 1 << shard;
+        }
+        if let Some(destination) = transaction.destination {
+            if kind == "transfer" && self.shard_of(destination) != shard {
+                let destinations = self.transfers.entry(tx).or_default();
+                if !destinations.contains(&destination) {
+                    destinations.push(destination);
+                }
+            }
+        }
+        Ok(Ok(shard))
+    }
+}
+
+impl Shard {
+    /// Starts a thread handling requests against a bank, sending any
+    /// rejected transactions back, and giving the bank back once the
+    /// sender is dropped.
+    fn spawn(mut bank: Bank, rejections: Sender<Rejected>) -> Self {
+        let (sender, receiver) = sync_channel(QUEUE_LEN);
+        let handle = thread::spawn(move || {
+            let mut sequences = vec![];
+            for message in receiver {
+                match message {
+                    Message::Transaction {
+                        line,
+                        sequence,
+                        transaction,
+                        raw,
+                        latest,
+                    } => {
+                        // Undated disputes are checked against the latest
+                        // timestamp of every shard, not just this one.
+                        bank.set_latest_timestamp(latest);
+                        let audited = bank.audit_log().len();
+                        let result = bank.handle_transaction(transaction);
+                        // Number the admin actions it applied, so the audit
+                        // logs can be merged in order.
+                        sequences.extend((audited..bank.audit_log().len()).map(|_| sequence));
+                        if let Err(err) = result {
+                            // The receiver outlives the shards.
+                            let _ = rejections.send(Rejected {
+                                line,
+                                record: raw.text(),
+                                reason: err.code(),
+                                message: err.to_string(),
+                            });
+                        }
+                    }
+                    Message::Rejected { transaction, err } => {
+                        bank.record(&transaction, &Err(err));
+                    }
+                    Message::Lookup { tx, reply } => {
+                        let _ = reply.send(bank.get_logged_transaction(tx));
+                    }
+                    Message::Take { client, reply } => {
+                        let _ = reply.send(bank.take_client(client));
+                    }
+                    Message::Give {
+                        client,
+                        account,
+                        history,
+                    } => bank.give_client(client, account, history),
+                    Message::Flush { reply } => {
+                        let _ = reply.send(());
+                    }
+                }
+            }
+            (bank, sequences)
+        });
+
+        Self { sender, handle }
+    }
+
+    /// Queues a request for the shard.
+    ///
+    /// Returns an Err if the shard has stopped.
+    fn send(&self, message: Message) -> Result<()> {
+        self.sender.send(message).map_err(|_| stopped())
+    }
+
+    /// Takes a client's account and history out of the shard once it has
+    /// handled every transaction queued before.
+    ///
+    /// Returns an Err if the shard has stopped.
+    fn take(&self, client: u16) -> Result<(Option<Account>, Vec<HistoryEvent>)> {
+        let (reply, answer) = channel();
+        self.send(Message::Take { client, reply })?;
+        answer.recv().map_err(|_| stopped())
+    }
+
+    /// Looks up a logged transaction once the shard has handled every
+    /// transaction queued before.
+    ///
+    /// Returns an Err if the shard has stopped.
+    fn lookup(&self, tx: u32) -> Result<Option<LoggedTransaction>> {
+        let (reply, answer) = channel();
+        self.send(Message::Lookup { tx, reply })?;
+        answer.recv().map_err(|_| stopped())
+    }
+}
+
+/// The error for a shard thread that stopped unexpectedly.
+fn stopped() -> Error {
+    Error::msg("A shard stopped unexpectedly")
+}
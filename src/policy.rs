@@ -0,0 +1,42 @@
+/// A Policy holds the configurable rules a Bank applies when
+/// handling transactions. The default policy matches the rules
+/// described in the README.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    /// Whether withdrawals can be disputed as well as deposits.
+    ///
+    /// A disputed withdrawal holds the withdrawn amount as a
+    /// provisional credit. Resolving it lets the withdrawal stand
+    /// and drops the credit, while a chargeback reverses the
+    /// withdrawal, making the funds available again and locking
+    /// the account.
+    pub dispute_withdrawals: bool,
+    /// Whether the portion of a transaction that was disputed and then
+    /// resolved can be disputed again. The rest of it always can be.
+    pub redispute_resolved: bool,
+    /// Whether accounts that are locked or frozen can still take
+    /// deposits. Every other transaction is rejected on a locked
+    /// account, and closed accounts take nothing.
+    pub locked_deposits: bool,
+    /// How many seconds after a transaction it can be disputed, if
+    /// disputes are limited. Only disputes of transactions with a
+    /// timestamp are checked, and disputes without one are checked as of
+    /// the latest timestamp seen.
+    pub dispute_window: Option<u64>,
+    /// Whether admin operations (unlock, freeze and close) are
+    /// accepted. They are rejected by default, so that an ordinary
+    /// input or server client can't change the status of accounts.
+    pub allow_admin: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            dispute_withdrawals: false,
+            redispute_resolved: true,
+            locked_deposits: true,
+            dispute_window: None,
+            allow_admin: false,
+        }
+    }
+}
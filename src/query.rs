@@ -0,0 +1,140 @@
+use crate::decoder::{DecodedRow, Decoder};
+use anyhow::{Error, Result};
+use std::fmt::{Display, Formatter};
+
+/// A point in the inputs, which the state of a bank can be queried at
+/// by processing the inputs up to and including it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsOf {
+    /// After every row of an input that started on or before a line,
+    /// numbered the way rejections are, so the header of a CSV input is
+    /// line 1. Rows that fail to parse are included, and a line past
+    /// the end of the input is after its last row.
+    Line { input: String, line: u64 },
+    /// After the first row with a tx ID.
+    Tx(u32),
+}
+
+impl Display for AsOf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Line { input, line } => write!(f, "line {} of {}", line, input),
+            Self::Tx(tx) => write!(f, "tx {}", tx),
+        }
+    }
+}
+
+/// A Cutoff ends a set of inputs once a point in them is reached, so
+/// that a bank processing them holds the state as of that point:
+/// ```text
+/// let mut cutoff = Cutoff::new(AsOf::Tx(1234));
+/// bank.process_decoder("monday.csv", cutoff.decoder("monday.csv", monday))?;
+/// if !cutoff.reached() {
+///     bank.process_decoder("tuesday.csv", cutoff.decoder("tuesday.csv", tuesday))?;
+/// }
+/// cutoff.check()?;
+/// let account = bank.get_account(7);
+/// ```
+/// Rows are handled exactly as they would be without the cutoff, as it
+/// only stops the inputs early.
+#[derive(Clone, Debug)]
+pub struct Cutoff {
+    as_of: AsOf,
+    rows: u64,
+    reached: bool,
+}
+
+impl Cutoff {
+    /// Create a Cutoff at a point in the inputs.
+    pub fn new(as_of: AsOf) -> Self {
+        Self {
+            as_of,
+            rows: 0,
+            reached: false,
+        }
+    }
+
+    /// Returns the point the inputs are cut off at.
+    pub fn as_of(&self) -> &AsOf {
+        &self.as_of
+    }
+
+    /// Returns whether the point was reached, so later rows are cut off.
+    pub fn reached(&self) -> bool {
+        self.reached
+    }
+
+    /// Returns the number of rows read through the cutoff.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Checks that the point was reached once every input is processed.
+    ///
+    /// Returns an Err if the inputs ended before the point.
+    pub fn check(&self) -> Result<()> {
+        if self.reached {
+            return Ok(());
+        }
+        Err(Error::msg(format!(
+            "The inputs end after row {}, before {}",
+            self.rows, self.as_of
+        )))
+    }
+
+    /// Wraps the decoder of the next input, named the way it is in
+    /// `AsOf::Line`, ending it once the point is reached.
+    pub fn decoder<D: Decoder>(&mut self, input: &str, decoder: D) -> CutoffDecoder<'_, D> {
+        let line = match &self.as_of {
+            AsOf::Line { input: name, line } if name == input => Some(*line),
+            _ => None,
+        };
+        CutoffDecoder {
+            cutoff: self,
+            line,
+            decoder,
+        }
+    }
+}
+
+/// A CutoffDecoder decodes the rows of an input up to a Cutoff.
+pub struct CutoffDecoder<'a, D> {
+    cutoff: &'a mut Cutoff,
+    /// The last line of this input rows are decoded up to, if the
+    /// point is in it.
+    line: Option<u64>,
+    decoder: D,
+}
+
+impl<D: Decoder> Decoder for CutoffDecoder<'_, D> {
+    fn next_row(&mut self) -> Option<Result<DecodedRow>> {
+        if self.cutoff.reached {
+            return None;
+        }
+
+        let Some(row) = self.decoder.next_row() else {
+            // The end of the input the point is in is past every line
+            // before it.
+            self.cutoff.reached = self.line.is_some();
+            return None;
+        };
+        if let Ok(row) = &row {
+            let cutoff = &mut *self.cutoff;
+            // A row starting after the line is the first one past the
+            // point, so it is left out.
+            if self.line.is_some_and(|line| row.line > line) {
+                cutoff.reached = true;
+                return None;
+            }
+            cutoff.rows += 1;
+            cutoff.reached = match cutoff.as_of {
+                AsOf::Line { .. } => self.line == Some(row.line),
+                AsOf::Tx(tx) => row
+                    .transaction
+                    .as_ref()
+                    .is_ok_and(|transaction| transaction.tx == tx),
+            };
+        }
+        Some(row)
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::{Error, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Reason code for input rows that fail to parse as a Transaction.
+pub const PARSE_ERROR: &str = "parse_error";
+
+/// The file format rejected rows are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectsFormat {
+    /// Comma separated values with a header row.
+    Csv,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl RejectsFormat {
+    /// Guesses the format from a file extension, defaulting to CSV.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("ndjson") | Some("jsonl") => Self::Ndjson,
+            _ => Self::Csv,
+        }
+    }
+}
+
+impl FromStr for RejectsFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            unknown => Err(Error::msg(format!("Unknown rejects format '{}'", unknown))),
+        }
+    }
+}
+
+/// A Rejection is a single input row that was skipped, along
+/// with why it was skipped.
+#[derive(Serialize, Debug)]
+pub struct Rejection<'a> {
+    /// The input the row was read from.
+    pub source: &'a str,
+    /// The line of the input the row started on.
+    pub line: u64,
+    /// A machine-readable code for why the row was rejected.
+    pub reason: &'a str,
+    /// The row as it was read from the input.
+    pub record: &'a str,
+    /// A human readable description of the failure.
+    pub message: String,
+}
+
+/// A RejectsWriter writes every rejected input row to a sink, so
+/// rows can be investigated or re-driven later.
+///
+/// CSV output looks like:
+/// ```text
+/// source,line,reason,record,message
+/// in.csv,4,not_deposit,"dispute,1,2",Transaction 2 is not deposit
+/// ```
+/// NDJSON output looks like:
+/// ```text
+/// {"source":"in.csv","line":4,"reason":"not_deposit","record":"dispute,1,2","message":"..."}
+/// ```
+pub struct RejectsWriter {
+    sink: Sink,
+}
+
+/// The underlying writer for each RejectsFormat.
+enum Sink {
+    Csv(Box<csv::Writer<Box<dyn Write + Send>>>),
+    Ndjson(Box<dyn Write + Send>),
+}
+
+impl RejectsWriter {
+    /// Create a RejectsWriter over any writer.
+    pub fn new<W: Write + Send + 'static>(writer: W, format: RejectsFormat) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        let sink = match format {
+            RejectsFormat::Csv => Sink::Csv(Box::new(csv::Writer::from_writer(writer))),
+            RejectsFormat::Ndjson => Sink::Ndjson(writer),
+        };
+        Self { sink }
+    }
+
+    /// Creates or truncates a file at `path` to write rejections to.
+    ///
+    /// Returns an Err if the file can't be created.
+    pub fn create<P: AsRef<Path>>(path: P, format: RejectsFormat) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), format))
+    }
+
+    /// Writes a single rejection.
+    ///
+    /// Returns an Err if writing to the sink fails.
+    pub fn write(&mut self, rejection: &Rejection) -> Result<()> {
+        match &mut self.sink {
+            // The csv writer writes the header before the first row, and
+            // quotes the record as it contains commas.
+            Sink::Csv(writer) => writer.serialize(rejection)?,
+            Sink::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, rejection)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes any buffered rejections to the sink.
+    pub fn flush(&mut self) -> Result<()> {
+        match &mut self.sink {
+            Sink::Csv(writer) => writer.flush()?,
+            Sink::Ndjson(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+}
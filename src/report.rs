@@ -0,0 +1,176 @@
+use crate::account::Account;
+use crate::bank::Bank;
+use crate::store::{AccountStore, TransactionStore};
+use anyhow::{Error, Result};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The format a report is displayed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Comma and space separated values with a header row.
+    #[default]
+    Csv,
+    /// A single JSON array of account snapshots.
+    Json,
+    /// One JSON account snapshot per line.
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            unknown => Err(Error::msg(format!("Unknown output format '{}'", unknown))),
+        }
+    }
+}
+
+/// The account field a report is sorted by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Client,
+    Total,
+    Available,
+    Held,
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "client" => Ok(Self::Client),
+            "total" => Ok(Self::Total),
+            "available" => Ok(Self::Available),
+            "held" => Ok(Self::Held),
+            unknown => Err(Error::msg(format!("Unknown sort key '{}'", unknown))),
+        }
+    }
+}
+
+/// The direction a report is sorted in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl FromStr for SortOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "asc" | "ascending" => Ok(Self::Ascending),
+            "desc" | "descending" => Ok(Self::Descending),
+            unknown => Err(Error::msg(format!("Unknown sort order '{}'", unknown))),
+        }
+    }
+}
+
+/// Compares two accounts by a key in an order. Accounts that are
+/// equal by the key are always ordered by ascending client ID, so
+/// the ordering is total and output is stable between runs.
+pub fn compare_accounts(a: &Account, b: &Account, key: SortKey, order: SortOrder) -> Ordering {
+    let ordering = match key {
+        SortKey::Client => Ordering::Equal,
+        SortKey::Total => a.get_total().ok().cmp(&b.get_total().ok()),
+        SortKey::Available => a.available.cmp(&b.available),
+        SortKey::Held => a.held.cmp(&b.held),
+    };
+    let ordering = match order {
+        SortOrder::Ascending => ordering,
+        SortOrder::Descending => ordering.reverse(),
+    };
+
+    // Client IDs break ties, and follow the order when sorting by client.
+    ordering.then_with(|| match (key, order) {
+        (SortKey::Client, SortOrder::Descending) => b.client().cmp(&a.client()),
+        _ => a.client().cmp(&b.client()),
+    })
+}
+
+/// A Report displays the accounts of a bank sorted by a key,
+/// in a CSV format by default:
+/// ```text
+/// client, available, held, total, locked
+/// 1, 1.5000, 0.0000, 1.5000, false
+/// 2, 2.0000, 0.1234, 2.1234, false
+/// ```
+/// Or in a JSON format as AccountSnapshots, either as one array
+/// or one snapshot per line:
+/// ```text
+/// {"client":1,"available":"1.5000","held":"0.0000","total":"1.5000","locked":false}
+/// {"client":2,"available":"2.0000","held":"0.1234","total":"2.1234","locked":false}
+/// ```
+pub struct Report<'a> {
+    accounts: Vec<&'a Account>,
+    format: OutputFormat,
+}
+
+impl<'a> Report<'a> {
+    /// Create a CSV report of a bank's accounts sorted by a key in an order.
+    pub fn new<T: TransactionStore, A: AccountStore>(
+        bank: &'a Bank<T, A>,
+        key: SortKey,
+        order: SortOrder,
+    ) -> Self {
+        Self {
+            accounts: bank.sorted_accounts(key, order),
+            format: OutputFormat::Csv,
+        }
+    }
+
+    /// Only keeps the account of a client in the report.
+    pub fn only(mut self, client: u16) -> Self {
+        self.accounts.retain(|account| account.client() == client);
+        self
+    }
+
+    /// Sets the format the report is displayed in.
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Display for Report<'_> {
+    /// Note that a bank devoid of accounts will only print a header in
+    /// CSV, an empty array in JSON, and nothing in NDJSON.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.format {
+            OutputFormat::Csv => {
+                writeln!(f, "client, available, held, total, locked")?;
+                // Loop through all accounts in order and print them.
+                for account in &self.accounts {
+                    writeln!(f, "{}", account)?;
+                }
+            }
+            OutputFormat::Json => {
+                let snapshots = self
+                    .accounts
+                    .iter()
+                    .map(|account| account.snapshot())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| std::fmt::Error)?;
+                let json = serde_json::to_string(&snapshots).map_err(|_| std::fmt::Error)?;
+                writeln!(f, "{}", json)?;
+            }
+            OutputFormat::Ndjson => {
+                for account in &self.accounts {
+                    let snapshot = account.snapshot().map_err(|_| std::fmt::Error)?;
+                    let json = serde_json::to_string(&snapshot).map_err(|_| std::fmt::Error)?;
+                    writeln!(f, "{}", json)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use crate::account::AccountSnapshot;
+use crate::bank::Bank;
+use crate::decoder::{DecodedRow, Decoder, InputFormat};
+use crate::error::EngineError;
+use crate::rejects::PARSE_ERROR;
+use crate::report::{SortKey, SortOrder};
+use crate::store::{AccountStore, MemoryAccountStore, MemoryTransactionStore, TransactionStore};
+use crate::transaction::unix_now;
+use anyhow::{Error, Result};
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A Server keeps a Bank in memory and accepts transactions and queries
+/// over TCP, handling every connection on its own thread. Requests are
+/// one per line, and every request gets exactly one JSON reply line:
+/// ```text
+/// deposit, 1, 1, 1.5
+/// {"status":"accepted","tx":1}
+/// {"type": "withdrawal", "client": 1, "tx": 2, "amount": "5.0"}
+/// {"status":"rejected","tx":2,"reason":"insufficient_funds","message":"..."}
+/// account 1
+/// {"status":"ok","account":{"client":1,"available":"1.5000",...}}
+/// accounts
+/// {"status":"ok","accounts":[{"client":1,...}]}
+/// ```
+/// Transactions are CSV rows without a header, or JSON objects like
+/// NDJSON input. Rows that fail to parse and unknown commands reply with
+/// an `error` status. Rejected rows are also reported like rows of an
+/// input, named after the client's address.
+pub struct Server<T = MemoryTransactionStore, A = MemoryAccountStore>
+where
+    T: TransactionStore + Send + 'static,
+    A: AccountStore + Send + 'static,
+{
+    listener: TcpListener,
+    bank: Arc<Mutex<Bank<T, A>>>,
+}
+
+impl<T, A> Server<T, A>
+where
+    T: TransactionStore + Send + 'static,
+    A: AccountStore + Send + 'static,
+{
+    /// Create a Server serving a bank on an address, such as
+    /// `127.0.0.1:7878`. Port 0 picks a free port.
+    ///
+    /// Returns an Err if the address can't be bound.
+    pub fn bind<S: ToSocketAddrs>(address: S, bank: Bank<T, A>) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            bank: Arc::new(Mutex::new(bank)),
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    ///
+    /// Returns an Err if the address can't be read.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Returns the bank being served, shared with the connections.
+    pub fn bank(&self) -> Arc<Mutex<Bank<T, A>>> {
+        Arc::clone(&self.bank)
+    }
+
+    /// Accepts connections until the listener fails, handling each on
+    /// its own thread.
+    ///
+    /// Returns an Err if accepting a connection fails.
+    pub fn run(self) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let bank = Arc::clone(&self.bank);
+            thread::spawn(move || {
+                if let Err(err) = serve_connection(stream, &bank) {
+                    eprintln!("Connection failed: {}", err);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Replies to every request on a connection until it closes.
+///
+/// Returns an Err if the connection fails.
+fn serve_connection<T, A>(stream: TcpStream, bank: &Mutex<Bank<T, A>>) -> Result<()>
+where
+    T: TransactionStore,
+    A: AccountStore,
+{
+    let source = stream.peer_addr()?.to_string();
+    let mut writer = stream.try_clone()?;
+
+    let mut line = 0;
+    for request in BufReader::new(stream).lines() {
+        let request = request?;
+        line += 1;
+        if request.trim().is_empty() {
+            continue;
+        }
+
+        let reply = handle_request(bank, &source, line, request.trim())?;
+        serde_json::to_writer(&mut writer, &reply)?;
+        writeln!(writer)?;
+    }
+
+    // Write out anything the connection left buffered.
+    lock(bank)?.flush()
+}
+
+/// A Reply answers a single request, serialized as a line of JSON.
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct Reply {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<AccountSnapshot>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts: Option<Vec<AccountSnapshot>>,
+}
+
+impl Reply {
+    /// Returns a reply for a request that could not be handled.
+    fn error(reason: &'static str, message: String) -> Self {
+        Self {
+            status: "error",
+            reason: Some(reason),
+            message: Some(message),
+            ..Default::default()
+        }
+    }
+}
+
+/// Handles a single request line, returning the reply.
+///
+/// Returns an Err if the bank can't be used anymore.
+pub(crate) fn handle_request<T, A>(
+    bank: &Mutex<Bank<T, A>>,
+    source: &str,
+    line: u64,
+    request: &str,
+) -> Result<Reply>
+where
+    T: TransactionStore,
+    A: AccountStore,
+{
+    let mut words = request.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("accounts"), None, _) => {
+            let bank = lock(bank)?;
+            let accounts = bank
+                .sorted_accounts(SortKey::Client, SortOrder::Ascending)
+                .into_iter()
+                .map(|account| account.snapshot())
+                .collect::<Result<Vec<_>, _>>();
+            Ok(match accounts {
+                Ok(accounts) => Reply {
+                    status: "ok",
+                    accounts: Some(accounts),
+                    ..Default::default()
+                },
+                Err(err) => Reply::error(err.code(), err.to_string()),
+            })
+        }
+        (Some("account"), Some(client), None) => {
+            let client: u16 = match client.parse() {
+                Ok(client) => client,
+                Err(_) => {
+                    return Ok(Reply::error(
+                        PARSE_ERROR,
+                        format!("Invalid client '{}'", client),
+                    ))
+                }
+            };
+            let bank = lock(bank)?;
+            Ok(
+                match bank.get_account(client).map(|account| account.snapshot()) {
+                    Some(Ok(account)) => Reply {
+                        status: "ok",
+                        account: Some(account),
+                        ..Default::default()
+                    },
+                    Some(Err(err)) => Reply::error(err.code(), err.to_string()),
+                    None => {
+                        let err = EngineError::UnknownAccount { client };
+                        Reply::error(err.code(), err.to_string())
+                    }
+                },
+            )
+        }
+        _ => handle_row(bank, source, line, request),
+    }
+}
+
+/// Decodes a transaction from a CSV row or JSON object and handles it.
+///
+/// Returns an Err if the bank can't be used anymore.
+fn handle_row<T, A>(
+    bank: &Mutex<Bank<T, A>>,
+    source: &str,
+    line: u64,
+    request: &str,
+) -> Result<Reply>
+where
+    T: TransactionStore,
+    A: AccountStore,
+{
+    // Decode the request the same way as a row of an input.
+    let (format, input) = if request.starts_with('{') {
+        (InputFormat::Ndjson, request.to_string())
+    } else {
+        let csv = format!(
+            "type, client, tx, amount, destination, expires, actor, reason, timestamp\n{}",
+            request
+        );
+        (InputFormat::Csv, csv)
+    };
+    let decoded = format.decoder(input.as_bytes())?.next_row();
+    let DecodedRow {
+        transaction, raw, ..
+    } = match decoded {
+        Some(row) => row?,
+        None => return Ok(Reply::error(PARSE_ERROR, "Empty request".to_string())),
+    };
+
+    let mut bank = lock(bank)?;
+    let transaction = match transaction {
+        Ok(transaction) => transaction,
+        Err(message) => {
+            bank.reject(source, line, &raw.text(), PARSE_ERROR, message.clone())?;
+            return Ok(Reply::error(PARSE_ERROR, message));
+        }
+    };
+
+    // Holds expire as time passes, so catch up before handling.
+    bank.expire_holds(unix_now())?;
+    if let Some(flag) = bank.check_order(&transaction) {
+        eprintln!("{}:{}: {}", source, line, flag);
+    }
+
+    let tx = transaction.tx;
+    Ok(match bank.handle_transaction(transaction) {
+        Ok(()) => Reply {
+            status: "accepted",
+            tx: Some(tx),
+            ..Default::default()
+        },
+        Err(err) => {
+            bank.reject(source, line, &raw.text(), err.code(), err.to_string())?;
+            Reply {
+                status: "rejected",
+                tx: Some(tx),
+                reason: Some(err.code()),
+                message: Some(err.to_string()),
+                ..Default::default()
+            }
+        }
+    })
+}
+
+/// Locks the bank.
+///
+/// Returns an Err if a connection panicked while holding the lock.
+fn lock<T>(bank: &Mutex<T>) -> Result<std::sync::MutexGuard<'_, T>> {
+    bank.lock()
+        .map_err(|_| Error::msg("The bank was poisoned by a failed connection"))
+}
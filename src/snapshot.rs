@@ -0,0 +1,199 @@
+use crate::account::{Account, AccountStatus};
+use crate::amount::Amount;
+use crate::audit::AuditEntry;
+use crate::fees::FeeEntry;
+use crate::history::HistoryEvent;
+use crate::ledger::Posting;
+use crate::transaction::{DisputeState, Hold, LoggedTransaction, OutOfOrder};
+use serde::{Deserialize, Serialize};
+
+/// The version of the snapshot format written by this build. It goes up
+/// whenever the format changes:
+/// ```text
+/// 1  accounts and logged transactions
+/// 2  transfers, holds, partial disputes, account statuses, the audit
+///    log, fees, the ledger, histories and timestamps
+/// 3  resolved portions of disputes
+/// ```
+/// Every field added since version 1 is optional, so snapshots of an
+/// older version are read with the state they don't have left empty.
+/// Snapshots of a newer version are refused rather than guessed at, as
+/// their state would be silently dropped.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// A Snapshot is the full state of a Bank as it is written to disk,
+/// as a single JSON document:
+/// ```text
+/// {
+///   "version": 3,
+///   "accounts": [
+///     {"client":1,"available":"1.5000","held":"0.0000","locked":false},
+///     {"client":2,"available":"0.5000","held":"0.0000","authorized":"1.0000","locked":false}
+///   ],
+///   "transactions": [
+///     {"tx":1,"client":1,"amount":"1.5000","deposit":true,"state":"undisputed"},
+///     {"tx":2,"client":1,"amount":"0.5000","deposit":false,"destination":2,
+///      "state":"disputed","disputed":"0.5000"},
+///     {"tx":3,"client":2,"amount":"1.0000","deposit":false,
+///      "hold":{"state":"authorized"},"state":"undisputed"}
+///   ]
+/// }
+/// ```
+/// Accounts are ordered by client ID and transactions by tx ID, so the
+/// same state always produces the same snapshot. Accounts that are not
+/// active also have a `status`, and the admin operations applied,
+/// fees charged, ledger postings and client histories are kept under
+/// `audit`, `fees`, `ledger` and `history` when there are any.
+///
+/// The policy and rejects sink of a Bank are configuration rather than
+/// state, and are not part of a snapshot.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Snapshot {
+    pub version: u32,
+    pub accounts: Vec<AccountRecord>,
+    pub transactions: Vec<TransactionRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audit: Vec<AuditEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fees: Vec<FeeEntry>,
+    /// Empty in snapshots from before the ledger or of banks that don't
+    /// keep one, whose balances are posted as opening balances when
+    /// read by a bank that does.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ledger: Vec<Posting>,
+    /// Ordered by client, and in the order they were handled for each.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoryEvent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub out_of_order: Vec<OutOfOrder>,
+}
+
+/// An account as it is stored in a snapshot. The total is left out
+/// as it is derived from the available and held funds.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct AccountRecord {
+    client: u16,
+    available: Amount,
+    held: Amount,
+    /// Left out when there are no authorized funds, as it is in
+    /// snapshots from before authorizations existed.
+    #[serde(default, skip_serializing_if = "Amount::is_zero")]
+    authorized: Amount,
+    locked: bool,
+    /// Left out for active accounts, and in snapshots from before
+    /// accounts could be frozen or closed, where `locked` is the status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<AccountStatus>,
+}
+
+impl From<&Account> for AccountRecord {
+    fn from(account: &Account) -> Self {
+        Self {
+            client: account.client(),
+            available: account.available,
+            held: account.held,
+            authorized: account.authorized,
+            locked: account.is_locked(),
+            status: (!account.status.is_active()).then_some(account.status),
+        }
+    }
+}
+
+impl From<AccountRecord> for Account {
+    fn from(record: AccountRecord) -> Self {
+        let mut account = Account::new(record.client);
+        account.available = record.available;
+        account.held = record.held;
+        account.authorized = record.authorized;
+        account.status = record.status.unwrap_or(match record.locked {
+            true => AccountStatus::Locked,
+            false => AccountStatus::Active,
+        });
+        account
+    }
+}
+
+/// A logged transaction as it is stored in a snapshot, along with
+/// the tx ID it is keyed by.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct TransactionRecord {
+    pub tx: u32,
+    client: u16,
+    amount: Amount,
+    deposit: bool,
+    /// Only transfers have a destination, and snapshots from before
+    /// transfers existed leave it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    destination: Option<u16>,
+    /// Only authorizations have a hold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hold: Option<Hold>,
+    state: DisputeState,
+    /// The disputed, resolved and charged back portions, which
+    /// snapshots from before partial disputes leave out. Version 2
+    /// snapshots leave out the resolved portion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disputed: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolved: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    charged_back: Option<Amount>,
+    /// Only transactions with a timestamp have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+}
+
+impl TransactionRecord {
+    /// Creates a record of a logged transaction keyed by tx.
+    pub fn new(tx: u32, logged: &LoggedTransaction) -> Self {
+        Self {
+            tx,
+            client: logged.client,
+            amount: logged.amount,
+            deposit: logged.deposit,
+            destination: logged.destination,
+            hold: logged.hold,
+            state: logged.state,
+            disputed: (!logged.disputed.is_zero()).then_some(logged.disputed),
+            resolved: (!logged.resolved.is_zero()).then_some(logged.resolved),
+            charged_back: (!logged.charged_back.is_zero()).then_some(logged.charged_back),
+            timestamp: logged.timestamp,
+        }
+    }
+}
+
+impl From<TransactionRecord> for LoggedTransaction {
+    fn from(record: TransactionRecord) -> Self {
+        // Without the portions, a transaction was disputed or charged
+        // back as a whole. Without the resolved portion, a resolved
+        // transaction had all of what is left resolved.
+        let whole = |state| match record.state == state {
+            true => record.amount,
+            false => Amount::default(),
+        };
+        let charged_back = record
+            .charged_back
+            .unwrap_or_else(|| whole(DisputeState::ChargedBack));
+        let resolved = record.resolved.unwrap_or_else(|| {
+            whole(DisputeState::Resolved)
+                .checked_sub(charged_back)
+                .unwrap_or_default()
+        });
+        Self {
+            client: record.client,
+            amount: record.amount,
+            deposit: record.deposit,
+            destination: record.destination,
+            hold: record.hold,
+            state: record.state,
+            disputed: record
+                .disputed
+                .unwrap_or_else(|| whole(DisputeState::Disputed)),
+            resolved,
+            charged_back,
+            timestamp: record.timestamp,
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use crate::account::Account;
+use crate::amount::Amount;
+use crate::transaction::{DisputeState, Hold, HoldState, LoggedTransaction};
+use anyhow::{Error, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// A TransactionStore holds the log of past transactions a Bank
+/// refers back to for disputes, keyed by tx ID.
+///
+/// Logged transactions are returned by value, so a store is free to
+/// keep them anywhere, such as on disk.
+pub trait TransactionStore {
+    /// Returns the logged transaction with a tx ID, if there is one.
+    ///
+    /// Returns an Err if the store can't be read.
+    fn get(&self, tx: u32) -> Result<Option<LoggedTransaction>>;
+
+    /// Logs a transaction under a tx ID, replacing any transaction
+    /// already logged under it.
+    ///
+    /// Returns an Err if the store can't be written to.
+    fn insert(&mut self, tx: u32, logged: LoggedTransaction) -> Result<()>;
+
+    /// Removes the transaction logged under a tx ID, if any.
+    ///
+    /// Returns an Err if the store can't be written to.
+    fn remove(&mut self, tx: u32) -> Result<()>;
+
+    /// Removes every logged transaction.
+    ///
+    /// Returns an Err if the store can't be written to.
+    fn clear(&mut self) -> Result<()>;
+
+    /// Returns the number of logged transactions.
+    fn len(&self) -> usize;
+
+    /// Returns true if no transactions are logged.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over every logged transaction in tx ID order.
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<(u32, LoggedTransaction)>> + '_>;
+}
+
+/// Keeps the transaction log in memory. Fast, but uses memory for
+/// every logged transaction.
+#[derive(Default)]
+pub struct MemoryTransactionStore {
+    transactions: HashMap<u32, LoggedTransaction>,
+}
+
+impl TransactionStore for MemoryTransactionStore {
+    fn get(&self, tx: u32) -> Result<Option<LoggedTransaction>> {
+        Ok(self.transactions.get(&tx).cloned())
+    }
+
+    fn insert(&mut self, tx: u32, logged: LoggedTransaction) -> Result<()> {
+        self.transactions.insert(tx, logged);
+        Ok(())
+    }
+
+    fn remove(&mut self, tx: u32) -> Result<()> {
+        self.transactions.remove(&tx);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.transactions.clear();
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<(u32, LoggedTransaction)>> + '_> {
+        let mut txs: Vec<u32> = self.transactions.keys().copied().collect();
+        txs.sort_unstable();
+        Box::new(
+            txs.into_iter()
+                .map(move |tx| Ok((tx, self.transactions[&tx].clone()))),
+        )
+    }
+}
+
+/// An AccountStore holds the accounts of a Bank, keyed by client ID.
+///
+/// Accounts are handed out by reference, so a store keeps the accounts
+/// it is working with in memory. Stores backed by something else, such
+/// as a database file, write their changes out when `persist` is called.
+pub trait AccountStore {
+    /// Returns the account of a client, if there is one.
+    fn get(&self, client: u16) -> Option<&Account>;
+
+    /// Returns the account of a client, creating an empty one if
+    /// there isn't one.
+    fn get_or_create(&mut self, client: u16) -> &mut Account;
+
+    /// Stores an account under its client ID, replacing any account
+    /// already stored under it.
+    fn insert(&mut self, account: Account);
+
+    /// Removes the account of a client, if any.
+    fn remove(&mut self, client: u16);
+
+    /// Removes every account.
+    fn clear(&mut self);
+
+    /// Returns the number of accounts.
+    fn len(&self) -> usize;
+
+    /// Returns true if there are no accounts.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over every account, in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_>;
+
+    /// Writes out any changes to the accounts. Stores kept entirely in
+    /// memory have nothing to do.
+    ///
+    /// Returns an Err if the changes can't be written.
+    fn persist(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps accounts in memory in a map, using memory for only the
+/// clients that have an account.
+#[derive(Default)]
+pub struct MemoryAccountStore {
+    accounts: HashMap<u16, Account>,
+}
+
+impl AccountStore for MemoryAccountStore {
+    fn get(&self, client: u16) -> Option<&Account> {
+        self.accounts.get(&client)
+    }
+
+    fn get_or_create(&mut self, client: u16) -> &mut Account {
+        self.accounts
+            .entry(client)
+            .or_insert_with(|| Account::new(client))
+    }
+
+    fn insert(&mut self, account: Account) {
+        self.accounts.insert(account.client(), account);
+    }
+
+    fn remove(&mut self, client: u16) {
+        self.accounts.remove(&client);
+    }
+
+    fn clear(&mut self) {
+        self.accounts.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.accounts.values())
+    }
+}
+
+/// Keeps accounts in memory in a slot per possible client ID, so
+/// lookups are a single index with no hashing. Takes memory for
+/// all 65536 client IDs up front.
+pub struct DenseAccountStore {
+    accounts: Vec<Option<Account>>,
+    len: usize,
+}
+
+impl Default for DenseAccountStore {
+    fn default() -> Self {
+        Self {
+            accounts: vec![None; usize::from(u16::MAX) + 1],
+            len: 0,
+        }
+    }
+}
+
+impl AccountStore for DenseAccountStore {
+    fn get(&self, client: u16) -> Option<&Account> {
+        self.accounts[usize::from(client)].as_ref()
+    }
+
+    fn get_or_create(&mut self, client: u16) -> &mut Account {
+        let slot = &mut self.accounts[usize::from(client)];
+        if slot.is_none() {
+            self.len += 1;
+        }
+        slot.get_or_insert_with(|| Account::new(client))
+    }
+
+    fn insert(&mut self, account: Account) {
+        let slot = &mut self.accounts[usize::from(account.client())];
+        if slot.is_none() {
+            self.len += 1;
+        }
+        *slot = Some(account);
+    }
+
+    fn remove(&mut self, client: u16) {
+        if self.accounts[usize::from(client)].take().is_some() {
+            self.len -= 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.accounts.fill(None);
+        self.len = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.accounts.iter().flatten())
+    }
+}
+
+/// The size of a logged transaction on disk.
+const RECORD_LEN: u64 = 56;
+
+/// The number of records in a page. Pages that were never written to
+/// are skipped when iterating.
+const PAGE_RECORDS: u64 = 4096;
+
+/// The number of pages covering the full u32 tx space.
+const PAGES: u64 = (u32::MAX as u64 + 1) / PAGE_RECORDS;
+
+/// Keeps the transaction log in a file, using a fixed amount of
+/// memory however many transactions are logged.
+///
+/// Each tx ID has a fixed size record at `tx * 56` bytes into the
+/// file, so lookups are a single read. Records are laid out as:
+/// ```text
+/// flags: u8 (1 = logged, 2 = deposit, 4 = transfer,
+///            8 = authorization, 16 = expires, 32 = timestamp),
+/// state: u8, client: u16, amount: i64, destination: u16,
+/// hold state: u8, 1 byte of padding, expires: u64,
+/// disputed: i64, charged back: i64, timestamp: u64, resolved: i64,
+/// all little endian
+/// ```
+/// The destination is only set for transfers, the hold state and
+/// expiry for authorizations, and the timestamp for transactions
+/// that have one.
+///
+/// Unlogged tx IDs are left as holes, so on filesystems that support
+/// sparse files the file only takes up space for logged transactions,
+/// even when tx IDs span the full u32 range.
+///
+/// Besides the file, the store only keeps a bitmap of which pages of
+/// 4096 records were written to, which is 128KiB for the full u32
+/// range, so iterating doesn't read through the holes.
+///
+/// The file is scratch space and is emptied when the store is
+/// created. State is carried between runs with snapshots.
+pub struct DiskTransactionStore {
+    file: File,
+    len: usize,
+    /// One past the highest tx ID that was ever logged, in records.
+    end: u64,
+    /// A bit per page, set if any record in the page was written.
+    pages: Vec<u64>,
+}
+
+impl DiskTransactionStore {
+    /// Creates an empty store in a file at a path, emptying the file
+    /// if it exists.
+    ///
+    /// Returns an Err if the file can't be created.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        Ok(Self {
+            file,
+            len: 0,
+            end: 0,
+            pages: vec![0; (PAGES / 64) as usize],
+        })
+    }
+
+    /// Reads the record of a tx ID. Records past the end of the file
+    /// read as unlogged.
+    fn read_record(&self, tx: u32) -> Result<[u8; RECORD_LEN as usize]> {
+        let mut record = [0; RECORD_LEN as usize];
+        if u64::from(tx) < self.end {
+            let mut file = &self.file;
+            file.seek(SeekFrom::Start(u64::from(tx) * RECORD_LEN))?;
+            file.read_exact(&mut record)?;
+        }
+        Ok(record)
+    }
+
+    /// Writes the record of a tx ID.
+    fn write_record(&mut self, tx: u32, record: &[u8; RECORD_LEN as usize]) -> Result<()> {
+        self.file
+            .seek(SeekFrom::Start(u64::from(tx) * RECORD_LEN))?;
+        self.file.write_all(record)?;
+        self.end = self.end.max(u64::from(tx) + 1);
+
+        let page = u64::from(tx) / PAGE_RECORDS;
+        self.pages[(page / 64) as usize] |= 1 << (page % 64);
+        Ok(())
+    }
+
+    /// Returns true if any record in a page was written.
+    fn page_written(&self, page: u64) -> bool {
+        self.pages[(page / 64) as usize] & (1 << (page % 64)) != 0
+    }
+}
+
+/// Encodes a logged transaction as a record.
+fn encode(logged: &LoggedTransaction) -> [u8; RECORD_LEN as usize] {
+    let mut record = [0; RECORD_LEN as usize];
+    record[0] = 1 | if logged.deposit { 2 } else { 0 };
+    if let Some(destination) = logged.destination {
+        record[0] |= 4;
+        record[12..14].copy_from_slice(&destination.to_le_bytes());
+    }
+    record[1] = match logged.state {
+        DisputeState::Undisputed => 0,
+        DisputeState::Disputed => 1,
+        DisputeState::Resolved => 2,
+        DisputeState::ChargedBack => 3,
+    };
+    record[2..4].copy_from_slice(&logged.client.to_le_bytes());
+    record[4..12].copy_from_slice(&logged.amount.raw().to_le_bytes());
+    if let Some(hold) = logged.hold {
+        record[0] |= 8;
+        record[14] = match hold.state {
+            HoldState::Authorized => 0,
+            HoldState::Captured => 1,
+            HoldState::Released => 2,
+            HoldState::Expired => 3,
+        };
+        if let Some(expires) = hold.expires {
+            record[0] |= 16;
+            record[16..24].copy_from_slice(&expires.to_le_bytes());
+        }
+    }
+    record[24..32].copy_from_slice(&logged.disputed.raw().to_le_bytes());
+    record[32..40].copy_from_slice(&logged.charged_back.raw().to_le_bytes());
+    if let Some(timestamp) = logged.timestamp {
+        record[0] |= 32;
+        record[40..48].copy_from_slice(&timestamp.to_le_bytes());
+    }
+    record[48..56].copy_from_slice(&logged.resolved.raw().to_le_bytes());
+    record
+}
+
+/// Decodes a record, returning None if it is unlogged.
+///
+/// Returns an Err if the record is corrupt.
+fn decode(record: &[u8]) -> Result<Option<LoggedTransaction>> {
+    if record[0] & 1 == 0 {
+        return Ok(None);
+    }
+    let state = match record[1] {
+        0 => DisputeState::Undisputed,
+        1 => DisputeState::Disputed,
+        2 => DisputeState::Resolved,
+        3 => DisputeState::ChargedBack,
+        unknown => return Err(Error::msg(format!("Corrupt dispute state {}", unknown))),
+    };
+    let client = u16::from_le_bytes([record[2], record[3]]);
+    let amount = |range: std::ops::Range<usize>| {
+        let mut raw = [0; 8];
+        raw.copy_from_slice(&record[range]);
+        Amount::from_raw(i64::from_le_bytes(raw))
+    };
+
+    let hold = if record[0] & 8 != 0 {
+        let state = match record[14] {
+            0 => HoldState::Authorized,
+            1 => HoldState::Captured,
+            2 => HoldState::Released,
+            3 => HoldState::Expired,
+            unknown => return Err(Error::msg(format!("Corrupt hold state {}", unknown))),
+        };
+        let mut expires = [0; 8];
+        expires.copy_from_slice(&record[16..24]);
+        Some(Hold {
+            state,
+            expires: (record[0] & 16 != 0).then(|| u64::from_le_bytes(expires)),
+        })
+    } else {
+        None
+    };
+    let mut timestamp = [0; 8];
+    timestamp.copy_from_slice(&record[40..48]);
+
+    Ok(Some(LoggedTransaction {
+        client,
+        amount: amount(4..12),
+        deposit: record[0] & 2 != 0,
+        destination: (record[0] & 4 != 0).then(|| u16::from_le_bytes([record[12], record[13]])),
+        hold,
+        state,
+        disputed: amount(24..32),
+        resolved: amount(48..56),
+        charged_back: amount(32..40),
+        timestamp: (record[0] & 32 != 0).then(|| u64::from_le_bytes(timestamp)),
+    }))
+}
+
+impl TransactionStore for DiskTransactionStore {
+    fn get(&self, tx: u32) -> Result<Option<LoggedTransaction>> {
+        decode(&self.read_record(tx)?)
+    }
+
+    fn insert(&mut self, tx: u32, logged: LoggedTransaction) -> Result<()> {
+        let new = self.get(tx)?.is_none();
+        self.write_record(tx, &encode(&logged))?;
+        if new {
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, tx: u32) -> Result<()> {
+        if self.get(tx)?.is_some() {
+            self.write_record(tx, &[0; RECORD_LEN as usize])?;
+            self.len -= 1;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.len = 0;
+        self.end = 0;
+        self.pages.fill(0);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<(u32, LoggedTransaction)>> + '_> {
+        Box::new(DiskIter {
+            store: self,
+            next: 0,
+            buffer: vec![],
+            offset: 0,
+            failed: false,
+        })
+    }
+}
+
+/// Iterates over the records of a DiskTransactionStore, reading
+/// them a page at a time and skipping unwritten pages and unlogged
+/// tx IDs.
+struct DiskIter<'a> {
+    store: &'a DiskTransactionStore,
+    /// The tx ID of the next record to return.
+    next: u64,
+    /// A chunk of read records, and where the record of `next` is in it.
+    buffer: Vec<u8>,
+    offset: usize,
+    failed: bool,
+}
+
+impl DiskIter<'_> {
+    /// Reads the rest of the page starting at `next`.
+    fn fill(&mut self) -> Result<()> {
+        let page_end = (self.next / PAGE_RECORDS + 1) * PAGE_RECORDS;
+        let count = page_end.min(self.store.end) - self.next;
+        self.buffer = vec![0; (count * RECORD_LEN) as usize];
+
+        let mut file = &self.store.file;
+        file.seek(SeekFrom::Start(self.next * RECORD_LEN))?;
+        file.read_exact(&mut self.buffer)?;
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+impl Iterator for DiskIter<'_> {
+    type Item = Result<(u32, LoggedTransaction)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed && self.next < self.store.end {
+            if self.offset == self.buffer.len() {
+                // Jump over pages that were never written to.
+                let page = self.next / PAGE_RECORDS;
+                if !self.store.page_written(page) {
+                    self.next = (page + 1) * PAGE_RECORDS;
+                    continue;
+                }
+                if let Err(err) = self.fill() {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+
+            let record = &self.buffer[self.offset..self.offset + RECORD_LEN as usize];
+            let tx = self.next as u32;
+            self.offset += RECORD_LEN as usize;
+            self.next += 1;
+            match decode(record) {
+                Ok(Some(logged)) => return Some(Ok((tx, logged))),
+                Ok(None) => continue,
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
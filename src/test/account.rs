@@ -1,88 +1,292 @@
-use crate::account::Account;
+use super::amount;
+use crate::account::{Account, AccountSnapshot, AccountStatus};
+use crate::error::EngineError;
 
 /// Deposit into an account
 #[test]
 fn deposit() {
     let mut account = Account::new(1);
-    account.deposit(1.0);
-    assert_eq!(account.available, 1.0);
-    assert_eq!(account.held, 0.0);
+    assert!(account.deposit(amount("1.0")).is_ok());
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.held, amount("0.0"));
 }
 
 /// Withdraw from an account
 #[test]
 fn withdraw() {
     let mut account = Account::new(1);
-    account.deposit(1.0);
+    assert!(account.deposit(amount("1.0")).is_ok());
     // Normal
-    assert!(account.withdraw(1.0).is_ok());
-    assert_eq!(account.available, 0.0);
-    assert_eq!(account.held, 0.0);
+    assert!(account.withdraw(amount("1.0")).is_ok());
+    assert_eq!(account.available, amount("0.0"));
+    assert_eq!(account.held, amount("0.0"));
     // Insufficient funds
-    assert!(account.withdraw(1.0).is_err());
+    assert_eq!(
+        account.withdraw(amount("1.0")),
+        Err(EngineError::InsufficientFunds {
+            client: 1,
+            requested: amount("1.0"),
+            available: amount("0.0"),
+        })
+    );
 
     // Locked
-    account.deposit(1.0);
-    account.locked = true;
-    assert!(account.withdraw(1.0).is_err());
+    assert!(account.deposit(amount("1.0")).is_ok());
+    account.status = AccountStatus::Locked;
+    assert_eq!(
+        account.withdraw(amount("1.0")),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
 }
 
 /// A dispute on an account
 #[test]
 fn dispute() {
     let mut account = Account::new(1);
-    account.deposit(1.0);
-    assert!(account.dispute(1.0).is_ok());
-    assert_eq!(account.held, 1.0);
+    assert!(account.deposit(amount("1.0")).is_ok());
+    assert!(account.dispute(amount("1.0")).is_ok());
+    assert_eq!(account.held, amount("1.0"));
     // Insufficient funds
-    assert!(account.dispute(1.0).is_err());
-    assert_eq!(account.held, 1.0);
+    assert!(account.dispute(amount("1.0")).is_err());
+    assert_eq!(account.held, amount("1.0"));
 
     // Locked
-    account.deposit(1.0);
-    account.locked = true;
-    assert!(account.dispute(1.0).is_err());
+    assert!(account.deposit(amount("1.0")).is_ok());
+    account.status = AccountStatus::Locked;
+    assert!(account.dispute(amount("1.0")).is_err());
 }
 
 /// Resolving disputes
 #[test]
 fn resolve() {
     let mut account = Account::new(1);
-    account.held = 1.0;
-    assert!(account.resolve(1.0).is_ok());
-    assert_eq!(account.available, 1.0);
+    account.held = amount("1.0");
+    assert!(account.resolve(amount("1.0")).is_ok());
+    assert_eq!(account.available, amount("1.0"));
     // Insufficient funds
-    assert!(account.resolve(1.0).is_err());
-    assert_eq!(account.available, 1.0);
+    assert_eq!(
+        account.resolve(amount("1.0")),
+        Err(EngineError::InsufficientHeldFunds {
+            client: 1,
+            requested: amount("1.0"),
+            held: amount("0.0"),
+        })
+    );
+    assert_eq!(account.available, amount("1.0"));
 
-    // Locked
-    account.held = 1.0;
-    account.locked = true;
-    assert!(account.resolve(1.0).is_err());
+    // Locked by a chargeback, the rest of a dispute can still be resolved
+    account.held = amount("2.0");
+    account.status = AccountStatus::Locked;
+    assert!(account.resolve(amount("1.0")).is_ok());
+    assert_eq!(account.available, amount("2.0"));
+
+    // Frozen
+    account.status = AccountStatus::Frozen;
+    assert_eq!(
+        account.resolve(amount("1.0")),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
 }
 
 /// Chargebacks
 #[test]
 fn chargeback() {
     let mut account = Account::new(1);
-    account.held = 1.0;
-    assert!(account.chargeback(1.0).is_ok());
-    assert_eq!(account.held, 0.0);
+    account.held = amount("1.0");
+    assert!(account.chargeback(amount("1.0")).is_ok());
+    assert_eq!(account.held, amount("0.0"));
     // Insufficient funds
-    assert!(account.chargeback(1.0).is_err());
-    assert_eq!(account.held, 0.0);
+    assert!(account.chargeback(amount("1.0")).is_err());
+    assert_eq!(account.held, amount("0.0"));
 
-    // Locked
-    account.held = 1.0;
-    account.locked = true;
-    assert!(account.chargeback(1.0).is_err());
+    // Locked by a chargeback, the rest of a dispute can still be charged back
+    account.held = amount("2.0");
+    account.status = AccountStatus::Locked;
+    assert!(account.chargeback(amount("1.0")).is_ok());
+    assert_eq!(account.held, amount("1.0"));
+
+    // Frozen
+    account.status = AccountStatus::Frozen;
+    assert!(account.chargeback(amount("1.0")).is_err());
+    assert_eq!(account.held, amount("1.0"));
 }
 
 /// Test the total calculation
 #[test]
 fn get_total() {
     let mut account = Account::new(1);
-    account.deposit(2.0);
-    assert!(account.dispute(1.0).is_ok());
-    assert_eq!(account.get_total(), 2.0);
+    assert!(account.deposit(amount("2.0")).is_ok());
+    assert!(account.dispute(amount("1.0")).is_ok());
+    assert_eq!(account.get_total().unwrap(), amount("2.0"));
+}
+
+/// Balances that would overflow are rejected
+#[test]
+fn overflow() {
+    let mut account = Account::new(1);
+    assert!(account.deposit(amount("922337203685477.5807")).is_ok());
+    assert_eq!(
+        account.deposit(amount("0.0001")),
+        Err(EngineError::Overflow { client: 1 })
+    );
+    assert_eq!(account.available, amount("922337203685477.5807"));
+}
+
+/// Changes that would leave a total out of range are rejected, and a
+/// total set out of range directly still displays
+#[test]
+fn overflow_total() {
+    let mut account = Account::new(1);
+    assert!(account.deposit(amount("900000000000000.0")).is_ok());
+    assert!(account.dispute(amount("900000000000000.0")).is_ok());
+    assert_eq!(
+        account.deposit(amount("900000000000000.0")),
+        Err(EngineError::Overflow { client: 1 })
+    );
+    assert_eq!(
+        account.dispute_withdrawal(amount("900000000000000.0")),
+        Err(EngineError::Overflow { client: 1 })
+    );
+    assert_eq!(account.available, amount("0.0"));
+    assert!(account.get_total().is_ok());
+
+    account.available = amount("900000000000000.0");
+    assert_eq!(
+        account.to_string(),
+        "1, 900000000000000.0000, 900000000000000.0000, 1800000000000000.0000, false"
+    );
+}
+
+/// Locked accounts can be unlocked, any open account frozen, and only
+/// empty accounts closed, after which nothing succeeds
+#[test]
+fn admin() {
+    let mut account = Account::new(1);
+    assert_eq!(
+        account.unlock(),
+        Err(EngineError::AccountNotLocked { client: 1 })
+    );
+
+    account.status = AccountStatus::Locked;
+    assert!(account.unlock().is_ok());
+    assert_eq!(account.status, AccountStatus::Active);
+    assert!(account.freeze().is_ok());
+    assert_eq!(
+        account.withdraw(amount("0.0")),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+    assert!(account.unlock().is_ok());
+
+    assert!(account.deposit(amount("1.0")).is_ok());
+    assert_eq!(
+        account.close(),
+        Err(EngineError::AccountNotEmpty {
+            client: 1,
+            total: amount("1.0"),
+        })
+    );
+    assert!(account.withdraw(amount("1.0")).is_ok());
+    assert!(account.close().is_ok());
+    assert!(account.is_locked());
+    for result in [
+        account.deposit(amount("1.0")),
+        account.unlock(),
+        account.freeze(),
+        account.close(),
+    ] {
+        assert_eq!(result, Err(EngineError::AccountClosed { client: 1 }));
+    }
+}
+
+/// Snapshots serialize amounts as exact strings
+#[test]
+fn snapshot() {
+    let mut account = Account::new(3);
+    assert!(account.deposit(amount("2.1234")).is_ok());
+    assert!(account.dispute(amount("0.1234")).is_ok());
+
+    let snapshot = account.snapshot().unwrap();
+
+    assert_eq!(
+        snapshot,
+        AccountSnapshot {
+            client: 3,
+            available: amount("2.0"),
+            held: amount("0.1234"),
+            authorized: amount("0.0"),
+            total: amount("2.1234"),
+            locked: false,
+            status: AccountStatus::Active,
+        }
+    );
+    assert_eq!(
+        serde_json::to_string(&snapshot).unwrap(),
+        r#"{"client":3,"available":"2.0000","held":"0.1234","total":"2.1234","locked":false}"#
+    );
+}
+
+/// Disputing withdrawals through to a chargeback
+#[test]
+fn dispute_withdrawal() {
+    let mut account = Account::new(1);
+    assert!(account.dispute_withdrawal(amount("1.0")).is_ok());
+    assert_eq!(account.available, amount("0.0"));
+    assert_eq!(account.held, amount("1.0"));
+
+    assert!(account.resolve_withdrawal(amount("1.0")).is_ok());
+    assert_eq!(account.held, amount("0.0"));
+    // Insufficient funds
+    assert!(account.resolve_withdrawal(amount("1.0")).is_err());
+    assert!(account.chargeback_withdrawal(amount("1.0")).is_err());
+
+    assert!(account.dispute_withdrawal(amount("1.0")).is_ok());
+    assert!(account.chargeback_withdrawal(amount("1.0")).is_ok());
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert!(account.is_locked());
+
+    // Locked
+    assert!(account.dispute_withdrawal(amount("1.0")).is_err());
+}
+
+/// Authorized funds are kept apart from disputed funds until they are
+/// captured or released
+#[test]
+fn authorize() {
+    let mut account = Account::new(1);
+    assert!(account.deposit(amount("3.0")).is_ok());
+    assert!(account.dispute(amount("1.0")).is_ok());
+    assert!(account.authorize(amount("1.5")).is_ok());
+    assert_eq!(account.available, amount("0.5"));
+    assert_eq!(account.held, amount("1.0"));
+    assert_eq!(account.authorized, amount("1.5"));
+    assert_eq!(account.get_total().unwrap(), amount("3.0"));
+
+    // Resolving only touches disputed funds
+    assert!(account.resolve(amount("1.0")).is_ok());
+    assert!(account.resolve(amount("1.0")).is_err());
+    assert_eq!(account.authorized, amount("1.5"));
+
+    assert!(account.capture(amount("1.0")).is_ok());
+    assert!(account.release(amount("0.5")).is_ok());
+    assert_eq!(account.available, amount("2.0"));
+    assert_eq!(account.authorized, amount("0.0"));
+    // Insufficient funds
+    assert_eq!(
+        account.capture(amount("0.5")),
+        Err(EngineError::InsufficientAuthorizedFunds {
+            client: 1,
+            requested: amount("0.5"),
+            authorized: amount("0.0"),
+        })
+    );
+    assert!(account.authorize(amount("2.5")).is_err());
+
+    // Locked accounts can release, but not authorize or capture
+    assert!(account.authorize(amount("1.0")).is_ok());
+    account.status = AccountStatus::Locked;
+    assert!(account.authorize(amount("0.5")).is_err());
+    assert!(account.capture(amount("0.5")).is_err());
+    assert!(account.release(amount("1.0")).is_ok());
+    assert_eq!(account.available, amount("2.0"));
 }
@@ -0,0 +1,75 @@
+use super::amount;
+use crate::amount::Amount;
+use std::str::FromStr;
+
+/// Parsing the accepted decimal formats
+#[test]
+fn parse() {
+    assert_eq!(amount("1"), amount("1.0000"));
+    assert_eq!(amount("1."), amount("1.0"));
+    assert_eq!(amount(".5"), amount("0.5"));
+    assert_eq!(amount("+2.5"), amount("2.5"));
+    assert!(amount("-1.0").is_negative());
+
+    assert!(Amount::from_str("").is_err());
+    assert!(Amount::from_str(".").is_err());
+    assert!(Amount::from_str("-").is_err());
+    assert!(Amount::from_str("1.0.0").is_err());
+    assert!(Amount::from_str("1e4").is_err());
+    assert!(Amount::from_str("abc").is_err());
+}
+
+/// More than four decimals is rejected instead of rounded
+#[test]
+fn parse_too_many_decimals() {
+    assert!(Amount::from_str("0.0001").is_ok());
+    assert!(Amount::from_str("0.00001").is_err());
+    assert!(Amount::from_str("1.23456").is_err());
+}
+
+/// Amounts outside of the representable range fail to parse
+#[test]
+fn parse_out_of_range() {
+    assert!(Amount::from_str("922337203685477.5807").is_ok());
+    assert!(Amount::from_str("922337203685477.5808").is_err());
+    assert!(Amount::from_str("99999999999999999999").is_err());
+}
+
+/// Displaying always uses four decimal places
+#[test]
+fn display() {
+    assert_eq!(amount("1").to_string(), "1.0000");
+    assert_eq!(amount("0.002").to_string(), "0.0020");
+    assert_eq!(amount("-0.5").to_string(), "-0.5000");
+    assert_eq!(amount("1234.5678").to_string(), "1234.5678");
+}
+
+/// Arithmetic is exact and reports overflow
+#[test]
+fn checked() {
+    // 0.1 + 0.2 is exactly 0.3, unlike with floats
+    assert_eq!(
+        amount("0.1").checked_add(amount("0.2")),
+        Some(amount("0.3"))
+    );
+    assert_eq!(
+        amount("0.3").checked_sub(amount("0.1")),
+        Some(amount("0.2"))
+    );
+
+    let max = amount("922337203685477.5807");
+    assert!(max.checked_add(amount("0.0001")).is_none());
+    assert!(amount("-922337203685477.5807")
+        .checked_sub(amount("0.0002"))
+        .is_none());
+}
+
+/// Many small deposits do not drift
+#[test]
+fn no_drift() {
+    let mut total = Amount::default();
+    for _ in 0..100_000 {
+        total = total.checked_add(amount("0.0001")).unwrap();
+    }
+    assert_eq!(total, amount("10"));
+}
@@ -0,0 +1,607 @@
+use super::amount;
+use crate::account::AccountStatus;
+use crate::bank::Bank;
+use crate::error::EngineError;
+use crate::policy::Policy;
+use crate::transaction::{DisputeState, HoldState, Transaction};
+
+/// Each rejection path reports a distinct error
+#[test]
+fn errors() {
+    let mut bank = Bank::new();
+    let deposit = |tx, amount| Transaction::new("deposit", 1, tx, amount);
+
+    assert!(bank
+        .handle_transaction(deposit(1, Some(amount("1.0"))))
+        .is_ok());
+    assert_eq!(
+        bank.handle_transaction(deposit(1, Some(amount("1.0")))),
+        Err(EngineError::DuplicateTransaction { tx: 1 })
+    );
+    assert_eq!(
+        bank.handle_transaction(deposit(2, None)),
+        Err(EngineError::MissingAmount { tx: 2 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("withdrawal", 1, 3, Some(amount("5.0")))),
+        Err(EngineError::InsufficientFunds {
+            client: 1,
+            requested: amount("5.0"),
+            available: amount("1.0"),
+        })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 9, None)),
+        Err(EngineError::UnknownTransaction { tx: 9 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 2, 1, None)),
+        Err(EngineError::ClientMismatch {
+            tx: 1,
+            client: 2,
+            expected: 1,
+        })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("resolve", 1, 1, None)),
+        Err(EngineError::NotDisputed { tx: 1 })
+    );
+    assert!(bank
+        .handle_transaction(Transaction::new("dispute", 1, 1, None))
+        .is_ok());
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 1, None)),
+        Err(EngineError::AlreadyDisputed { tx: 1 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("destroy", 1, 4, None)),
+        Err(EngineError::UnsupportedType {
+            tx: 4,
+            kind: "destroy".to_string(),
+        })
+    );
+}
+
+/// Withdrawals can't be disputed
+#[test]
+fn errors_not_deposit() {
+    let mut bank = Bank::new();
+
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 1, Some(amount("2.0"))))
+        .is_ok());
+    assert!(bank
+        .handle_transaction(Transaction::new("withdrawal", 1, 2, Some(amount("1.0"))))
+        .is_ok());
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 2, None)),
+        Err(EngineError::NotDeposit { tx: 2 })
+    );
+}
+
+/// Locked accounts reject withdrawals
+#[test]
+fn errors_locked() {
+    let mut bank = Bank::new();
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("2.0"))),
+        Transaction::new("dispute", 1, 1, None),
+        Transaction::new("chargeback", 1, 1, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("withdrawal", 1, 2, Some(amount("1.0")))),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+}
+
+/// Resolved transactions can be disputed again by default, but
+/// charged back transactions are final
+#[test]
+fn dispute_states() {
+    let mut bank = Bank::new();
+    let state = |bank: &Bank| bank.get_logged_transaction(1).unwrap().state;
+
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 1, Some(amount("2.0"))))
+        .is_ok());
+    for (kind, expected) in [
+        ("dispute", DisputeState::Disputed),
+        ("resolve", DisputeState::Resolved),
+        ("dispute", DisputeState::Disputed),
+        ("chargeback", DisputeState::ChargedBack),
+    ] {
+        assert!(bank
+            .handle_transaction(Transaction::new(kind, 1, 1, None))
+            .is_ok());
+        assert_eq!(state(&bank), expected);
+    }
+    for kind in ["dispute", "resolve", "chargeback"] {
+        assert_eq!(
+            bank.handle_transaction(Transaction::new(kind, 1, 1, None)),
+            Err(EngineError::AlreadyChargedBack { tx: 1 })
+        );
+    }
+    assert_eq!(state(&bank), DisputeState::ChargedBack);
+}
+
+/// Transactions stay disputed until none of them is, and partial
+/// amounts can't be more than the portion they act on
+#[test]
+fn partial_disputes() {
+    let mut bank = Bank::new();
+    let logged = |bank: &Bank, tx| bank.get_logged_transaction(tx).unwrap();
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("2.0"))),
+        Transaction::new("deposit", 1, 3, Some(amount("1.0"))),
+        Transaction::transfer(1, 2, 2, amount("1.0")),
+        Transaction::new("dispute", 1, 1, Some(amount("0.5"))),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 1, Some(amount("1.5001")))),
+        Err(EngineError::InvalidDisputeAmount {
+            tx: 1,
+            requested: amount("1.5001"),
+            limit: amount("1.5"),
+        })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("resolve", 1, 1, Some(amount("0.0")))),
+        Err(EngineError::InvalidDisputeAmount {
+            tx: 1,
+            requested: amount("0.0"),
+            limit: amount("0.5"),
+        })
+    );
+
+    // Without an amount, the rest of it is disputed and then resolved.
+    assert!(bank
+        .handle_transaction(Transaction::new("resolve", 1, 1, Some(amount("0.25"))))
+        .is_ok());
+    assert_eq!(logged(&bank, 1).state, DisputeState::Disputed);
+    assert!(bank
+        .handle_transaction(Transaction::new("dispute", 1, 1, None))
+        .is_ok());
+    assert_eq!(logged(&bank, 1).disputed, amount("2.0"));
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 1, None)),
+        Err(EngineError::AlreadyDisputed { tx: 1 })
+    );
+    assert!(bank
+        .handle_transaction(Transaction::new("resolve", 1, 1, None))
+        .is_ok());
+    assert_eq!(logged(&bank, 1).state, DisputeState::Resolved);
+
+    // Transfers are charged back in part from the destination.
+    for transaction in [
+        Transaction::new("dispute", 1, 2, Some(amount("0.75"))),
+        Transaction::new("chargeback", 1, 2, Some(amount("0.25"))),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    let destination = bank.get_account(2).unwrap();
+    assert_eq!(destination.available, amount("0.25"));
+    assert_eq!(destination.held, amount("0.5"));
+    assert!(destination.is_locked());
+    assert_eq!(bank.get_account(1).unwrap().available, amount("2.25"));
+    assert_eq!(logged(&bank, 2).charged_back, amount("0.25"));
+    assert_eq!(logged(&bank, 2).state, DisputeState::Disputed);
+
+    // The destination is locked, but the rest of the dispute is settled.
+    for transaction in [
+        Transaction::new("resolve", 1, 2, Some(amount("0.25"))),
+        Transaction::new("chargeback", 1, 2, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    let destination = bank.get_account(2).unwrap();
+    assert_eq!(destination.available, amount("0.5"));
+    assert_eq!(destination.held, amount("0.0"));
+    assert!(destination.is_locked());
+    assert_eq!(bank.get_account(1).unwrap().available, amount("2.5"));
+    assert_eq!(logged(&bank, 2).charged_back, amount("0.5"));
+    assert_eq!(logged(&bank, 2).state, DisputeState::ChargedBack);
+}
+
+/// Transactions that move funds are rejected unless their amount is
+/// positive, and leave the account as it was
+#[test]
+fn errors_invalid_amount() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 1, Some(amount("10.0"))))
+        .is_ok());
+
+    for (tx, kind, value) in [
+        (2, "deposit", "-3.0"),
+        (3, "deposit", "0.0"),
+        (4, "withdrawal", "-5.0"),
+        (5, "withdrawal", "0.0"),
+        (6, "authorize", "-1.0"),
+        (7, "authorize", "0.0"),
+    ] {
+        assert_eq!(
+            bank.handle_transaction(Transaction::new(kind, 1, tx, Some(amount(value)))),
+            Err(EngineError::InvalidAmount {
+                tx,
+                amount: amount(value)
+            })
+        );
+    }
+    let account = bank.get_account(1).unwrap();
+    assert_eq!(account.available, amount("10.0"));
+    assert_eq!(account.authorized, amount("0.0"));
+    assert_eq!(bank.num_logs(), 1);
+}
+
+/// A deposit that would leave the total out of range is rejected, so the
+/// report can always be printed
+#[test]
+fn errors_total_overflow() {
+    let mut bank = Bank::new();
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("900000000000000.0"))),
+        Transaction::new("dispute", 1, 1, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new(
+            "deposit",
+            1,
+            2,
+            Some(amount("900000000000000.0"))
+        )),
+        Err(EngineError::Overflow { client: 1 })
+    );
+    assert!(bank.get_account(1).unwrap().snapshot().is_ok());
+    assert_eq!(
+        bank.to_string(),
+        "client, available, held, total, locked\n\
+         1, 0.0000, 900000000000000.0000, 900000000000000.0000, false\n"
+    );
+}
+
+/// Resolved transactions can't be disputed again when the policy
+/// forbids it
+#[test]
+fn errors_redispute() {
+    let mut bank = Bank::with_policy(Policy {
+        redispute_resolved: false,
+        ..Default::default()
+    });
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("2.0"))),
+        Transaction::new("dispute", 1, 1, None),
+        Transaction::new("resolve", 1, 1, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 1, None)),
+        Err(EngineError::AlreadyResolved { tx: 1 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("resolve", 1, 1, None)),
+        Err(EngineError::NotDisputed { tx: 1 })
+    );
+    assert_eq!(bank.get_account(1).unwrap().available, amount("2.0"));
+
+    // Only the resolved portion is barred, not the rest never disputed.
+    for transaction in [
+        Transaction::new("deposit", 1, 2, Some(amount("2.0"))),
+        Transaction::new("dispute", 1, 2, Some(amount("0.5"))),
+        Transaction::new("resolve", 1, 2, None),
+        Transaction::new("dispute", 1, 2, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    let logged = bank.get_logged_transaction(2).unwrap();
+    assert_eq!(logged.disputed, amount("1.5"));
+    assert_eq!(logged.resolved, amount("0.5"));
+    assert_eq!(bank.get_account(1).unwrap().held, amount("1.5"));
+    assert!(bank
+        .handle_transaction(Transaction::new("resolve", 1, 2, None))
+        .is_ok());
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 2, Some(amount("0.1")))),
+        Err(EngineError::AlreadyResolved { tx: 2 })
+    );
+}
+
+/// Resolved portions are disputed again after the rest that was never
+/// disputed when the policy allows it
+#[test]
+fn redispute_resolved() {
+    let mut bank = Bank::new();
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("2.0"))),
+        Transaction::new("dispute", 1, 1, Some(amount("1.5"))),
+        Transaction::new("resolve", 1, 1, None),
+        Transaction::new("dispute", 1, 1, Some(amount("1.0"))),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    let logged = bank.get_logged_transaction(1).unwrap();
+    assert_eq!(logged.disputed, amount("1.0"));
+    assert_eq!(logged.resolved, amount("1.0"));
+    assert_eq!(logged.never_disputed(), amount("0.0"));
+
+    assert!(bank
+        .handle_transaction(Transaction::new("dispute", 1, 1, None))
+        .is_ok());
+    let logged = bank.get_logged_transaction(1).unwrap();
+    assert_eq!(logged.disputed, amount("2.0"));
+    assert_eq!(logged.resolved, amount("0.0"));
+}
+
+/// Disputes are only accepted within the dispute window, when both
+/// transactions have a timestamp
+#[test]
+fn errors_dispute_window() {
+    let mut bank = Bank::with_policy(Policy {
+        dispute_window: Some(60),
+        ..Default::default()
+    });
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("2.0"))).at(1000),
+        Transaction::new("deposit", 1, 2, Some(amount("1.0"))).at(1000),
+        Transaction::new("deposit", 1, 3, Some(amount("1.0"))),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("dispute", 1, 1, None).at(1061)),
+        Err(EngineError::DisputeWindowExpired { tx: 1, window: 60 })
+    );
+    assert!(bank
+        .handle_transaction(Transaction::new("dispute", 1, 2, None).at(1060))
+        .is_ok());
+    assert!(bank
+        .handle_transaction(Transaction::new("dispute", 1, 3, None).at(5000))
+        .is_ok());
+    assert!(bank
+        .handle_transaction(Transaction::new("dispute", 1, 1, None))
+        .is_ok());
+    assert_eq!(bank.get_account(1).unwrap().held, amount("4.0"));
+}
+
+/// Each transfer rejection path reports a distinct error, and leaves
+/// both accounts as they were
+#[test]
+fn errors_transfer() {
+    let mut bank = Bank::new();
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("2.0"))),
+        Transaction::new("deposit", 2, 2, Some(amount("922337203685477.5807"))),
+        Transaction::new("deposit", 3, 3, Some(amount("1.0"))),
+        Transaction::new("dispute", 3, 3, None),
+        Transaction::new("chargeback", 3, 3, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("transfer", 1, 4, Some(amount("1.0")))),
+        Err(EngineError::MissingDestination { tx: 4 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::transfer(1, 1, 4, amount("1.0"))),
+        Err(EngineError::SelfTransfer { tx: 4, client: 1 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::transfer(1, 3, 4, amount("1.0"))),
+        Err(EngineError::AccountLocked { client: 3 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::transfer(3, 1, 4, amount("1.0"))),
+        Err(EngineError::AccountLocked { client: 3 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::transfer(1, 2, 4, amount("1.0"))),
+        Err(EngineError::Overflow { client: 2 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::transfer(1, 2, 1, amount("1.0"))),
+        Err(EngineError::DuplicateTransaction { tx: 1 })
+    );
+
+    // A negative transfer would move funds out of the destination.
+    for value in ["-1.0", "0.0"] {
+        assert_eq!(
+            bank.handle_transaction(Transaction::transfer(1, 4, 4, amount(value))),
+            Err(EngineError::InvalidAmount {
+                tx: 4,
+                amount: amount(value)
+            })
+        );
+    }
+    assert!(bank.get_account(4).is_none());
+
+    assert_eq!(bank.get_account(1).unwrap().available, amount("2.0"));
+    assert_eq!(bank.num_logs(), 3);
+    assert!(bank
+        .handle_transaction(Transaction::transfer(1, 4, 4, amount("2.0")))
+        .is_ok());
+    assert_eq!(bank.get_account(1).unwrap().available, amount("0.0"));
+    assert_eq!(bank.get_account(4).unwrap().available, amount("2.0"));
+}
+
+/// Each admin rejection path reports a distinct error, and the policy
+/// decides whether admin operations are allowed and whether locked
+/// accounts take deposits
+#[test]
+fn errors_admin() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 1, Some(amount("1.0"))))
+        .is_ok());
+    assert_eq!(
+        bank.handle_transaction(Transaction::admin("freeze", 1, 2, "bob", Some("fraud"))),
+        Err(EngineError::AdminDisabled { tx: 2 })
+    );
+    assert!(!bank.get_account(1).unwrap().is_locked());
+    assert!(bank.audit_log().is_empty());
+
+    let mut bank = Bank::with_policy(Policy {
+        locked_deposits: false,
+        allow_admin: true,
+        ..Default::default()
+    });
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("1.0"))),
+        Transaction::new("deposit", 2, 2, Some(amount("1.0"))),
+        Transaction::new("withdrawal", 2, 3, Some(amount("1.0"))),
+        Transaction::admin("close", 2, 4, "bob", None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("unlock", 1, 5, None)),
+        Err(EngineError::MissingActor { tx: 5 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::admin("freeze", 1, 5, "bob", None)),
+        Err(EngineError::MissingReason { tx: 5 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::admin("unlock", 1, 5, "bob", None)),
+        Err(EngineError::AccountNotLocked { client: 1 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::admin("close", 1, 5, "bob", None)),
+        Err(EngineError::AccountNotEmpty {
+            client: 1,
+            total: amount("1.0"),
+        })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::admin("close", 3, 5, "bob", None)),
+        Err(EngineError::UnknownAccount { client: 3 })
+    );
+    assert!(bank.get_account(3).is_none());
+    assert_eq!(
+        bank.handle_transaction(Transaction::transfer(1, 2, 5, amount("1.0"))),
+        Err(EngineError::AccountClosed { client: 2 })
+    );
+
+    assert!(bank
+        .handle_transaction(Transaction::admin("freeze", 1, 5, "bob", Some("fraud")))
+        .is_ok());
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("deposit", 1, 6, Some(amount("1.0")))),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+    assert_eq!(bank.get_account(1).unwrap().available, amount("1.0"));
+    assert_eq!(bank.get_account(1).unwrap().status, AccountStatus::Frozen);
+    assert_eq!(bank.audit_log().len(), 2);
+}
+
+/// Holds expire once their time has passed, unless they were
+/// captured or released first
+#[test]
+fn expire_holds() {
+    let mut bank = Bank::new();
+    let hold = |bank: &Bank, tx| bank.get_logged_transaction(tx).unwrap().hold.unwrap();
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("3.0"))),
+        Transaction::authorize(1, 2, amount("1.0"), Some(100)),
+        Transaction::authorize(1, 3, amount("1.0"), Some(200)),
+        Transaction::authorize(1, 4, amount("1.0"), None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(bank.expire_holds(99), Ok(0));
+    assert_eq!(bank.expire_holds(150), Ok(1));
+    assert_eq!(hold(&bank, 2).state, HoldState::Expired);
+    assert_eq!(bank.get_account(1).unwrap().available, amount("1.0"));
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("capture", 1, 2, None)),
+        Err(EngineError::HoldNotActive { tx: 2 })
+    );
+
+    assert!(bank
+        .handle_transaction(Transaction::new("capture", 1, 3, None).at(150))
+        .is_ok());
+    assert_eq!(bank.expire_holds(u64::MAX), Ok(0));
+    assert_eq!(hold(&bank, 3).state, HoldState::Captured);
+    assert_eq!(hold(&bank, 4).state, HoldState::Authorized);
+
+    let account = bank.get_account(1).unwrap();
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.authorized, amount("1.0"));
+    assert_eq!(account.get_total().unwrap(), amount("2.0"));
+}
+
+/// Expiries from an input are refused unless the hold has reached its
+/// expiry by the time of the expiry, or else the current time
+#[test]
+fn errors_expire() {
+    let mut bank = Bank::new();
+    let hold = |bank: &Bank, tx| bank.get_logged_transaction(tx).unwrap().hold.unwrap();
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("3.0"))),
+        Transaction::authorize(1, 2, amount("1.0"), Some(u64::MAX)),
+        Transaction::authorize(1, 3, amount("1.0"), None),
+        Transaction::authorize(1, 4, amount("1.0"), Some(100)),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    for tx in [2, 3] {
+        assert_eq!(
+            bank.handle_transaction(Transaction::new("expire", 1, tx, None)),
+            Err(EngineError::HoldNotExpired { tx })
+        );
+        assert_eq!(hold(&bank, tx).state, HoldState::Authorized);
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("expire", 1, 4, None).at(99)),
+        Err(EngineError::HoldNotExpired { tx: 4 })
+    );
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("expire", 2, 4, None)),
+        Err(EngineError::ClientMismatch {
+            tx: 4,
+            client: 2,
+            expected: 1
+        })
+    );
+    assert_eq!(bank.get_account(1).unwrap().authorized, amount("3.0"));
+
+    assert!(bank
+        .handle_transaction(Transaction::new("expire", 1, 4, None).at(100))
+        .is_ok());
+    assert_eq!(hold(&bank, 4).state, HoldState::Expired);
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("expire", 1, 4, None)),
+        Err(EngineError::HoldNotActive { tx: 4 })
+    );
+    assert_eq!(bank.get_account(1).unwrap().available, amount("1.0"));
+}
+
+/// Accounts and logs can be read back after handling
+#[test]
+fn accessors() {
+    let mut bank = Bank::new();
+
+    assert!(bank
+        .deposit(Transaction::new("deposit", 7, 1, Some(amount("1.5"))))
+        .is_ok());
+
+    assert_eq!(bank.num_accounts(), 1);
+    assert_eq!(bank.num_logs(), 1);
+    assert_eq!(bank.get_account(7).unwrap().client(), 7);
+    assert_eq!(bank.get_logged_transaction(1).unwrap().client, 7);
+    assert!(bank.get_account(8).is_none());
+}
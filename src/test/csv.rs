@@ -1,31 +1,63 @@
-use crate::Bank;
-use std::path::PathBuf;
-use std::str::FromStr;
-
-macro_rules! test_file {
-    ($file:expr) => {
-        // PathBuf from_str is infallible
-        PathBuf::from_str(env!("CARGO_MANIFEST_DIR"))
-            .unwrap()
-            .join("src/test/csv/")
-            .join($file)
-    };
-}
+use super::amount;
+use crate::audit::AdminAction;
+use crate::{AccountStatus, Bank, DisputeState, HoldState, OutOfOrder, Policy, Transaction};
 
 /// Creates a new bank and processes transactions from a
 /// file in the src/test/csv/ directory.
 fn process(test_csv: &'static str) -> Bank {
-    let mut bank = Bank::new();
-    assert!(!bank.process_transactions(test_file!(test_csv)).is_err());
+    process_with_policy(test_csv, Policy::default())
+}
+
+/// Creates a new bank that applies a policy, keeps histories and
+/// processes transactions from a file in the src/test/csv/ directory.
+fn process_with_policy(test_csv: &'static str, policy: Policy) -> Bank {
+    let mut bank = Bank::with_policy(policy);
+    bank.keep_history();
+    assert!(bank.process_transactions(test_file!(test_csv)).is_ok());
     bank
 }
 
+/// A policy that allows disputing withdrawals.
+fn dispute_withdrawals() -> Policy {
+    Policy {
+        dispute_withdrawals: true,
+        ..Default::default()
+    }
+}
+
 /// Test regular processing
 #[test]
 fn processing() {
     process("processing.csv");
 }
 
+/// Transactions can be read from any reader
+#[test]
+fn reader() {
+    let mut bank = Bank::new();
+    let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, 2.0";
+    assert!(bank.process_reader("-", input.as_bytes()).is_ok());
+
+    let account = bank.get_account(1).unwrap();
+
+    assert_eq!(account.available, amount("3.0"));
+}
+
+/// Several inputs processed in sequence share the same state
+#[test]
+fn multiple_inputs() {
+    let mut bank = Bank::new();
+    assert!(bank.process_transactions(test_file!("deposit.csv")).is_ok());
+    let input = "type, client, tx, amount\nwithdrawal, 1, 3, 1.2345\ndispute, 1, 1";
+    assert!(bank.process_reader("-", input.as_bytes()).is_ok());
+
+    let account = bank.get_account(1).unwrap();
+
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.held, amount("1.0"));
+    assert_eq!(bank.num_logs(), 3);
+}
+
 /// A chargeback attempted with no dispute
 #[test]
 fn chargeback() {
@@ -34,10 +66,10 @@ fn chargeback() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_ne!(account.available, 0.0);
-    assert_eq!(account.held, 0.0);
-    assert!(!account.locked);
-    assert!(!tx.disputed);
+    assert_ne!(account.available, amount("0.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert!(!account.is_locked());
+    assert_eq!(tx.state, DisputeState::Undisputed);
 }
 
 /// A chargeback attempted with a dispute
@@ -48,10 +80,10 @@ fn chargeback_dispute() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_eq!(account.available, 0.0);
-    assert_eq!(account.held, 0.0);
-    assert!(account.locked);
-    assert!(tx.disputed);
+    assert_eq!(account.available, amount("0.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert!(account.is_locked());
+    assert_eq!(tx.state, DisputeState::ChargedBack);
 }
 
 /// A chargeback attempted with no valid transaction
@@ -62,10 +94,10 @@ fn chargeback_no_tx() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_eq!(account.available, 0.0);
-    assert_eq!(account.held, 1.0);
-    assert!(!account.locked);
-    assert!(tx.disputed);
+    assert_eq!(account.available, amount("0.0"));
+    assert_eq!(account.held, amount("1.0"));
+    assert!(!account.is_locked());
+    assert_eq!(tx.state, DisputeState::Disputed);
 }
 
 /// Test various decimal formats for sanity
@@ -77,9 +109,20 @@ fn decimals() {
     let tx_1 = bank.get_logged_transaction(1).unwrap();
     let tx_2 = bank.get_logged_transaction(2).unwrap();
 
-    assert!(account.available - 0.5575 < f32::EPSILON);
-    assert_eq!(tx_1.amount, 0.5555);
-    assert_eq!(tx_2.amount, 0.002);
+    assert_eq!(account.available, amount("0.5575"));
+    assert_eq!(tx_1.amount, amount("0.5555"));
+    assert_eq!(tx_2.amount, amount("0.002"));
+}
+
+/// Amounts with more than four decimals are rejected
+#[test]
+fn too_many_decimals() {
+    let bank = process("too_many_decimals.csv");
+
+    let account = bank.get_account(1).unwrap();
+
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(bank.num_logs(), 1);
 }
 
 /// A deposit into an account, this would create an account
@@ -90,7 +133,115 @@ fn deposit() {
 
     let account = bank.get_account(1).unwrap();
 
-    assert_eq!(account.available, 3.2345);
+    assert_eq!(account.available, amount("3.2345"));
+}
+
+/// A transfer moves funds between accounts, and is rejected as a
+/// whole when either side can't take part
+#[test]
+fn transfer() {
+    let bank = process("transfer.csv");
+
+    let source = bank.get_account(1).unwrap();
+    let destination = bank.get_account(2).unwrap();
+    let locked = bank.get_account(3).unwrap();
+    let tx = bank.get_logged_transaction(3).unwrap();
+
+    assert_eq!(source.available, amount("3.0"));
+    assert_eq!(destination.available, amount("2.0"));
+    assert_eq!(locked.get_total().unwrap(), amount("0.0"));
+    assert!(locked.is_locked());
+    assert_eq!(tx.destination, Some(2));
+    assert_eq!(bank.num_logs(), 3);
+}
+
+/// A disputed transfer holds the funds in the destination, and a
+/// chargeback returns them to the source and locks the destination
+#[test]
+fn transfer_dispute() {
+    let bank = process("transfer_dispute.csv");
+
+    let source = bank.get_account(1).unwrap();
+    let destination = bank.get_account(2).unwrap();
+    let tx = bank.get_logged_transaction(2).unwrap();
+
+    assert_eq!(source.available, amount("5.0"));
+    assert!(!source.is_locked());
+    assert_eq!(destination.get_total().unwrap(), amount("0.0"));
+    assert!(destination.is_locked());
+    assert_eq!(tx.state, DisputeState::ChargedBack);
+}
+
+/// Authorized funds are held until captured or released, and only
+/// authorizations can be captured or released
+#[test]
+fn authorize() {
+    let bank = process("authorize.csv");
+
+    let account = bank.get_account(1).unwrap();
+    let captured = bank.get_logged_transaction(2).unwrap().hold.unwrap();
+    let released = bank.get_logged_transaction(3).unwrap().hold.unwrap();
+    let expiring = bank.get_logged_transaction(5).unwrap().hold.unwrap();
+
+    assert_eq!(account.available, amount("2.5"));
+    assert_eq!(account.held, amount("0.0"));
+    assert_eq!(account.authorized, amount("0.5"));
+    assert_eq!(account.get_total().unwrap(), amount("3.0"));
+    assert_eq!(captured.state, HoldState::Captured);
+    assert_eq!(released.state, HoldState::Released);
+    assert_eq!(expiring.state, HoldState::Authorized);
+    assert_eq!(expiring.expires, Some(100));
+    assert_eq!(bank.num_logs(), 4);
+}
+
+/// Captures after a hold's expiry are refused within the same input,
+/// whether the expiry is checked against a timestamp or the current time
+#[test]
+fn capture_expired() {
+    let bank = process("capture_expired.csv");
+    let state = |tx| bank.get_logged_transaction(tx).unwrap().hold.unwrap().state;
+
+    let account = bank.get_account(1).unwrap();
+    assert_eq!(account.available, amount("3.0"));
+    assert_eq!(account.authorized, amount("0.0"));
+    assert_eq!(account.get_total().unwrap(), amount("3.0"));
+    assert_eq!(state(2), HoldState::Captured);
+    assert_eq!(state(3), HoldState::Expired);
+    assert_eq!(state(4), HoldState::Expired);
+    assert_eq!(
+        bank.history(1)[6].rejected.as_deref(),
+        Some("hold_not_active")
+    );
+}
+
+/// Admin operations unlock, freeze and close accounts, and are
+/// audited with who made them and why
+#[test]
+fn admin() {
+    let policy = Policy {
+        allow_admin: true,
+        ..Default::default()
+    };
+    let bank = process_with_policy("admin.csv", policy);
+
+    let frozen = bank.get_account(1).unwrap();
+    let closed = bank.get_account(2).unwrap();
+    let audit = bank.audit_log();
+
+    assert_eq!(frozen.available, amount("1.0"));
+    assert_eq!(frozen.status, AccountStatus::Frozen);
+    assert!(frozen.is_locked());
+    assert_eq!(closed.get_total().unwrap(), amount("0.0"));
+    assert_eq!(closed.status, AccountStatus::Closed);
+
+    assert_eq!(audit.len(), 3);
+    assert_eq!(audit[0].action, AdminAction::Unlock);
+    assert_eq!(audit[0].previous, AccountStatus::Locked);
+    assert_eq!(audit[1].action, AdminAction::Freeze);
+    assert_eq!(audit[1].actor, "alice");
+    assert_eq!(audit[1].reason.as_deref(), Some("suspected fraud"));
+    assert_eq!(audit[2].action, AdminAction::Close);
+    assert_eq!(audit[2].reason, None);
 }
 
 /// A dispute on a transaction
@@ -101,9 +252,9 @@ fn dispute() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_eq!(account.available, 0.0);
-    assert_eq!(account.held, 1.0);
-    assert!(tx.disputed);
+    assert_eq!(account.available, amount("0.0"));
+    assert_eq!(account.held, amount("1.0"));
+    assert_eq!(tx.state, DisputeState::Disputed);
 }
 
 /// A dispute on a withdrawal
@@ -113,8 +264,86 @@ fn dispute_withdrawal() {
 
     let account = bank.get_account(1).unwrap();
 
-    assert_eq!(account.available, 0.5);
-    assert_eq!(account.held, 0.0);
+    assert_eq!(account.available, amount("0.5"));
+    assert_eq!(account.held, amount("0.0"));
+}
+
+/// A dispute on a withdrawal when the policy allows it holds the
+/// withdrawn amount
+#[test]
+fn dispute_withdrawal_allowed() {
+    let bank = process_with_policy("dispute_withdrawal.csv", dispute_withdrawals());
+
+    let account = bank.get_account(1).unwrap();
+    let tx = bank.get_logged_transaction(2).unwrap();
+
+    assert_eq!(account.available, amount("0.5"));
+    assert_eq!(account.held, amount("1.0"));
+    assert_eq!(account.get_total().unwrap(), amount("1.5"));
+    assert_eq!(tx.state, DisputeState::Disputed);
+}
+
+/// A resolved withdrawal dispute lets the withdrawal stand
+#[test]
+fn resolve_dispute_withdrawal() {
+    let bank = process_with_policy("resolve_dispute_withdrawal.csv", dispute_withdrawals());
+
+    let account = bank.get_account(1).unwrap();
+    let tx = bank.get_logged_transaction(2).unwrap();
+
+    assert_eq!(account.available, amount("0.5"));
+    assert_eq!(account.held, amount("0.0"));
+    assert!(!account.is_locked());
+    assert_eq!(tx.state, DisputeState::Resolved);
+}
+
+/// A charged back withdrawal dispute reverses the withdrawal and
+/// locks the account
+#[test]
+fn chargeback_dispute_withdrawal() {
+    let bank = process_with_policy("chargeback_dispute_withdrawal.csv", dispute_withdrawals());
+
+    let account = bank.get_account(1).unwrap();
+
+    assert_eq!(account.available, amount("1.5"));
+    assert_eq!(account.held, amount("0.0"));
+    assert!(account.is_locked());
+}
+
+/// Disputes, resolves and chargebacks with an amount act on part of
+/// a transaction, and can't take more than is left. The rest of a
+/// dispute can still be settled once a partial chargeback locks the
+/// account
+#[test]
+fn partial_dispute() {
+    let mut bank = process("partial_dispute.csv");
+
+    let account = bank.get_account(1).unwrap();
+    let tx = bank.get_logged_transaction(1).unwrap();
+
+    assert_eq!(account.available, amount("5.0"));
+    assert_eq!(account.held, amount("2.0"));
+    assert_eq!(account.get_total().unwrap(), amount("7.0"));
+    assert!(account.is_locked());
+    assert_eq!(tx.disputed, amount("2.0"));
+    assert_eq!(tx.charged_back, amount("3.0"));
+    assert_eq!(tx.undisputed(), amount("5.0"));
+    assert_eq!(tx.state, DisputeState::Disputed);
+
+    for kind in ["resolve", "chargeback"] {
+        let settle = Transaction::new(kind, 1, 1, Some(amount("1.0")));
+        assert!(bank.handle_transaction(settle).is_ok());
+    }
+
+    let account = bank.get_account(1).unwrap();
+    let tx = bank.get_logged_transaction(1).unwrap();
+
+    assert_eq!(account.available, amount("6.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert!(account.is_locked());
+    assert_eq!(tx.disputed, amount("0.0"));
+    assert_eq!(tx.charged_back, amount("4.0"));
+    assert_eq!(tx.state, DisputeState::ChargedBack);
 }
 
 /// A dispute attempted with no transaction
@@ -125,9 +354,9 @@ fn dispute_no_tx() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_eq!(account.available, 1.0);
-    assert_eq!(account.held, 0.0);
-    assert!(!tx.disputed);
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert_eq!(tx.state, DisputeState::Undisputed);
 }
 
 /// A duplicate transaction, where the IDs are the same
@@ -137,7 +366,7 @@ fn duplicate_tx() {
 
     let account = bank.get_account(1).unwrap();
 
-    assert_eq!(account.available, 1.0);
+    assert_eq!(account.available, amount("1.0"));
     assert_eq!(bank.num_logs(), 2);
 }
 
@@ -175,8 +404,8 @@ fn misordered_client() {
     let account_1 = bank.get_account(1).unwrap();
     let account_2 = bank.get_account(2).unwrap();
 
-    assert_eq!(account_1.available, 3.0);
-    assert_eq!(account_2.available, 2.0);
+    assert_eq!(account_1.available, amount("3.0"));
+    assert_eq!(account_2.available, amount("2.0"));
 }
 
 /// Transaction IDs can come in any order
@@ -187,8 +416,8 @@ fn misordered_tx() {
     let account_1 = bank.get_account(1).unwrap();
     let account_2 = bank.get_account(2).unwrap();
 
-    assert_eq!(account_1.available, 2.0);
-    assert_eq!(account_2.available, 3.0);
+    assert_eq!(account_1.available, amount("2.0"));
+    assert_eq!(account_2.available, amount("3.0"));
 }
 
 /// Amounts are optional and lines without are still valid
@@ -230,9 +459,9 @@ fn resolve() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_eq!(account.available, 2.0);
-    assert_eq!(account.held, 0.0);
-    assert!(!tx.disputed);
+    assert_eq!(account.available, amount("2.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert_eq!(tx.state, DisputeState::Undisputed);
 }
 
 /// A resolve attempted with no transaction
@@ -243,9 +472,9 @@ fn resolve_no_tx() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_eq!(account.available, 0.0);
-    assert_eq!(account.held, 2.0);
-    assert!(tx.disputed);
+    assert_eq!(account.available, amount("0.0"));
+    assert_eq!(account.held, amount("2.0"));
+    assert_eq!(tx.state, DisputeState::Disputed);
 }
 
 /// A resolve attempted with a dispute
@@ -256,9 +485,9 @@ fn resolved_dispute() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(1).unwrap();
 
-    assert_eq!(account.available, 2.0);
-    assert_eq!(account.held, 0.0);
-    assert!(!tx.disputed);
+    assert_eq!(account.available, amount("2.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert_eq!(tx.state, DisputeState::Resolved);
 }
 
 /// Sample dataset
@@ -269,12 +498,12 @@ fn sample() {
     let account_1 = bank.get_account(1).unwrap();
     let account_2 = bank.get_account(2).unwrap();
 
-    assert_eq!(account_1.available, 1.5);
-    assert_eq!(account_1.held, 0.0);
-    assert!(!account_1.locked);
-    assert_eq!(account_2.available, 2.0);
-    assert_eq!(account_2.held, 0.0);
-    assert!(!account_2.locked);
+    assert_eq!(account_1.available, amount("1.5"));
+    assert_eq!(account_1.held, amount("0.0"));
+    assert!(!account_1.is_locked());
+    assert_eq!(account_2.available, amount("2.0"));
+    assert_eq!(account_2.held, amount("0.0"));
+    assert!(!account_2.is_locked());
     assert_eq!(bank.num_logs(), 4)
 }
 
@@ -285,8 +514,8 @@ fn withdrawal() {
 
     let account = bank.get_account(1).unwrap();
 
-    assert!(account.available - 0.4322 < f32::EPSILON);
-    assert_eq!(account.held, 0.0);
+    assert_eq!(account.available, amount("0.4322"));
+    assert_eq!(account.held, amount("0.0"));
 }
 
 /// A withdrawal that fails due to insufficient funds
@@ -296,8 +525,8 @@ fn withdrawal_insufficient() {
 
     let account = bank.get_account(1).unwrap();
 
-    assert_eq!(account.available, 1.0);
-    assert_eq!(account.held, 0.0);
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.held, amount("0.0"));
 }
 
 /// Total value calculation from an account
@@ -307,7 +536,7 @@ fn total() {
 
     let account = bank.get_account(1).unwrap();
 
-    assert!(account.get_total() - (account.available + account.held) < f32::EPSILON);
+    assert_eq!(account.get_total().unwrap(), amount("2.6666"));
 }
 
 /// A locked account attempting to be used
@@ -318,10 +547,10 @@ fn locked() {
     let account = bank.get_account(1).unwrap();
     let tx = bank.get_logged_transaction(2).unwrap();
 
-    assert!(account.locked);
-    assert_eq!(account.available, 1.5);
-    assert_eq!(account.held, 0.0);
-    assert!(!tx.disputed);
+    assert!(account.is_locked());
+    assert_eq!(account.available, amount("1.5"));
+    assert_eq!(account.held, amount("0.0"));
+    assert_eq!(tx.state, DisputeState::Undisputed);
 }
 
 /// Displaying the bank info
@@ -331,3 +560,41 @@ fn output() {
     let expected = "client, available, held, total, locked\n1, 3.2345, 0.0000, 3.2345, false\n";
     assert_eq!(format!("{}", bank), expected);
 }
+
+/// Disputes after the window are rejected, including ones without a
+/// timestamp as of the latest one seen, and transactions earlier than one
+/// already seen are flagged but still handled
+#[test]
+fn dispute_window() {
+    let policy = Policy {
+        dispute_window: Some(1000),
+        ..Default::default()
+    };
+    let bank = process_with_policy("dispute_window.csv", policy);
+
+    let account = bank.get_account(1).unwrap();
+    assert_eq!(account.available, amount("5.0"));
+    assert_eq!(account.held, amount("3.0"));
+    assert_eq!(
+        bank.history(1)[2].rejected.as_deref(),
+        Some("dispute_window_expired")
+    );
+    assert_eq!(bank.get_account(2).unwrap().held, amount("1.0"));
+    assert_eq!(
+        bank.out_of_order(),
+        [OutOfOrder {
+            tx: 2,
+            client: 1,
+            timestamp: 2500,
+            latest: 4000,
+        }]
+    );
+
+    let account = bank.get_account(3).unwrap();
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.held, amount("1.0"));
+    assert_eq!(
+        bank.history(3)[2].rejected.as_deref(),
+        Some("dispute_window_expired")
+    );
+}
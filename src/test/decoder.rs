@@ -0,0 +1,67 @@
+use super::amount;
+use crate::bank::Bank;
+use crate::decoder::{Decoder, InputFormat, NdjsonDecoder};
+use std::str::FromStr;
+
+/// NDJSON input is processed the same as the CSV sample
+#[test]
+fn ndjson() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .process_transactions(test_file!("sample.ndjson"))
+        .is_ok());
+
+    let account_1 = bank.get_account(1).unwrap();
+    let account_2 = bank.get_account(2).unwrap();
+
+    assert_eq!(account_1.available, amount("1.5"));
+    assert_eq!(account_1.held, amount("0.0"));
+    assert_eq!(account_2.available, amount("2.0"));
+    assert_eq!(bank.num_logs(), 4);
+}
+
+/// Malformed lines and inexact amounts are rejected, numbers are exact
+#[test]
+fn ndjson_rejects() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .process_transactions(test_file!("ndjson_rejects.ndjson"))
+        .is_ok());
+
+    let account = bank.get_account(1).unwrap();
+
+    // Only the last deposit succeeds, larger than an f64 can hold exactly.
+    assert_eq!(account.available, amount("123456789012.3456"));
+    assert_eq!(bank.num_logs(), 1);
+}
+
+/// Rows report the line they were on, skipping blank lines
+#[test]
+fn ndjson_lines() {
+    let input = "\n{\"type\": \"deposit\", \"client\": 1, \"tx\": 1}\n\nnot json\n";
+    let mut decoder = NdjsonDecoder::new(input.as_bytes());
+
+    let row = decoder.next_row().unwrap().unwrap();
+    assert_eq!(row.line, 2);
+    assert!(row.transaction.unwrap().amount.is_none());
+
+    let row = decoder.next_row().unwrap().unwrap();
+    assert_eq!(row.line, 4);
+    assert!(row.transaction.is_err());
+    assert_eq!(row.raw.text(), "not json");
+
+    assert!(decoder.next_row().is_none());
+}
+
+/// Formats are guessed from extensions
+#[test]
+fn input_format() {
+    assert_eq!(InputFormat::from_path("in.ndjson"), InputFormat::Ndjson);
+    assert_eq!(InputFormat::from_path("in.jsonl"), InputFormat::Ndjson);
+    assert_eq!(InputFormat::from_path("in.csv"), InputFormat::Csv);
+    assert_eq!(
+        InputFormat::from_str("NDJSON").unwrap(),
+        InputFormat::Ndjson
+    );
+    assert!(InputFormat::from_str("xml").is_err());
+}
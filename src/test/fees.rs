@@ -0,0 +1,232 @@
+use super::amount;
+use crate::bank::Bank;
+use crate::error::EngineError;
+use crate::fees::{FeeEntry, FeeKind, FeeRule, FeeSchedule};
+use crate::parallel::ShardedBank;
+use crate::transaction::Transaction;
+use crate::wal::Wal;
+use std::fs;
+
+/// Loads the fee schedule used by the fee tests.
+fn schedule() -> FeeSchedule {
+    FeeSchedule::load(test_file!("fee_schedule.csv")).unwrap()
+}
+
+/// Returns a fee entry of the ledger.
+fn entry(tx: u32, client: u16, r#type: &str, value: &str, reversal: bool) -> FeeEntry {
+    FeeEntry {
+        tx,
+        client,
+        r#type: r#type.to_string(),
+        amount: amount(value),
+        reversal,
+    }
+}
+
+/// Flat, percentage and tiered rules give the expected fees, and rules
+/// for a client replace the rules for everyone
+#[test]
+fn fee_schedule() {
+    let fees = schedule();
+    let fee = |kind, client, value| fees.fee(kind, client, amount(value)).unwrap();
+
+    assert_eq!(fee("deposit", 1, "200.0"), Some(amount("0.1")));
+    assert_eq!(fee("withdrawal", 1, "50.0"), Some(amount("0.5")));
+    assert_eq!(fee("withdrawal", 2, "50.0"), Some(amount("0.5")));
+    assert_eq!(fee("withdrawal", 2, "5000.0"), Some(amount("0.5")));
+    // 1% of 0.0055 is 0.000055, rounded to the nearest ten-thousandth.
+    assert_eq!(fee("withdrawal", 1, "0.0055"), Some(amount("0.0001")));
+    assert_eq!(fee("withdrawal", 1, "0.0049"), None);
+    assert_eq!(fee("transfer", 1, "100.0"), Some(amount("1.0")));
+    assert_eq!(fee("transfer", 1, "100.0001"), Some(amount("2.0")));
+    assert_eq!(fee("dispute", 1, "100.0"), None);
+
+    let tiers = FeeSchedule::new(vec![
+        FeeRule {
+            r#type: "Deposit".to_string(),
+            client: None,
+            fee: FeeKind::Flat,
+            value: amount("3.0"),
+            up_to: Some(amount("1000.0")),
+        },
+        FeeRule {
+            r#type: "deposit".to_string(),
+            client: None,
+            fee: FeeKind::Flat,
+            value: amount("1.0"),
+            up_to: Some(amount("10.0")),
+        },
+    ]);
+    assert_eq!(
+        tiers.fee("deposit", 1, amount("10.0")),
+        Ok(Some(amount("1.0")))
+    );
+    assert_eq!(
+        tiers.fee("deposit", 1, amount("11.0")),
+        Ok(Some(amount("3.0")))
+    );
+    assert_eq!(tiers.fee("deposit", 1, amount("1001.0")), Ok(None));
+
+    let too_much = FeeSchedule::new(vec![FeeRule {
+        r#type: "deposit".to_string(),
+        client: None,
+        fee: FeeKind::Percent,
+        value: amount("1000.0"),
+        up_to: None,
+    }]);
+    assert_eq!(
+        too_much.fee("deposit", 4, amount("900000000000000.0")),
+        Err(EngineError::Overflow { client: 4 })
+    );
+
+    let negative = "type, client, fee, value\ndeposit, , flat, -1.0\n";
+    assert!(FeeSchedule::read(negative.as_bytes()).is_err());
+    let unknown = "type, client, fee, value\ndeposit, , free, 1.0\n";
+    assert!(FeeSchedule::read(unknown.as_bytes()).is_err());
+}
+
+/// Handled transactions are charged their fees as ledger entries,
+/// rejected ones are not, and a chargeback reverses the fee of the
+/// transaction it charges back
+#[test]
+fn fees() {
+    let mut bank = Bank::new();
+    bank.set_fees(schedule());
+    assert!(bank.process_transactions(test_file!("fees.csv")).is_ok());
+
+    let client = bank.get_account(1).unwrap();
+    assert_eq!(client.available, amount("17.3"));
+    let client = bank.get_account(2).unwrap();
+    assert_eq!(client.available, amount("123.5"));
+    assert_eq!(client.held, amount("0.0"));
+    assert!(client.is_locked());
+
+    assert_eq!(
+        bank.fee_log(),
+        &[
+            entry(1, 1, "deposit", "0.1", false),
+            entry(2, 1, "withdrawal", "0.5", false),
+            entry(3, 2, "deposit", "0.1", false),
+            entry(4, 2, "withdrawal", "0.5", false),
+            entry(5, 1, "transfer", "0.1", false),
+            entry(6, 1, "transfer", "2.0", false),
+            entry(3, 2, "deposit", "0.1", true),
+            entry(3, 2, "chargeback", "5.0", false),
+        ]
+    );
+}
+
+/// Withdrawals, transfers and authorizations are rejected unless the
+/// available funds cover their fee too, and leave the account as it was
+#[test]
+fn fees_insufficient_funds() {
+    let mut bank = Bank::new();
+    bank.set_fees(schedule());
+    bank.keep_ledger();
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 1, Some(amount("50.1"))))
+        .is_ok());
+
+    for transaction in [
+        Transaction::new("withdrawal", 1, 2, Some(amount("50.0"))),
+        Transaction::transfer(1, 2, 2, amount("50.0")),
+    ] {
+        assert_eq!(
+            bank.handle_transaction(transaction),
+            Err(EngineError::InsufficientFunds {
+                client: 1,
+                requested: amount("50.5"),
+                available: amount("50.0"),
+            })
+        );
+    }
+    assert_eq!(bank.get_account(1).unwrap().available, amount("50.0"));
+    assert!(bank.get_account(2).is_none());
+    assert_eq!(bank.num_logs(), 1);
+    assert_eq!(bank.fee_log().len(), 1);
+
+    assert!(bank
+        .handle_transaction(Transaction::new("withdrawal", 1, 2, Some(amount("49.5"))))
+        .is_ok());
+    assert_eq!(bank.get_account(1).unwrap().available, amount("0.005"));
+    assert!(bank.verify_ledger().is_ok());
+}
+
+/// Percentage fees of disputes, resolves and chargebacks without an
+/// amount are charged on the portion they act on
+#[test]
+fn fees_dispute_portion() {
+    let rule = |r#type: &str| FeeRule {
+        r#type: r#type.to_string(),
+        client: None,
+        fee: FeeKind::Percent,
+        value: amount("10.0"),
+        up_to: None,
+    };
+    let mut bank = Bank::new();
+    bank.set_fees(FeeSchedule::new(vec![
+        rule("dispute"),
+        rule("resolve"),
+        rule("chargeback"),
+    ]));
+
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("100.0"))),
+        Transaction::new("dispute", 1, 1, Some(amount("40.0"))),
+        Transaction::new("resolve", 1, 1, Some(amount("10.0"))),
+        Transaction::new("chargeback", 1, 1, None),
+        Transaction::new("deposit", 2, 2, Some(amount("50.0"))),
+        Transaction::new("deposit", 2, 3, Some(amount("10.0"))),
+        Transaction::new("dispute", 2, 2, Some(amount("20.0"))),
+        Transaction::new("resolve", 2, 2, None),
+        Transaction::new("dispute", 2, 2, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.fee_log(),
+        &[
+            entry(1, 1, "dispute", "4.0", false),
+            entry(1, 1, "resolve", "1.0", false),
+            entry(1, 1, "chargeback", "3.0", false),
+            entry(2, 2, "dispute", "2.0", false),
+            entry(2, 2, "resolve", "2.0", false),
+            entry(2, 2, "dispute", "5.0", false),
+        ]
+    );
+}
+
+/// Replaying the write-ahead log and processing on several threads
+/// charge the same fees as processing on one
+#[test]
+fn fees_replay_sharded() {
+    let path = std::env::temp_dir().join(format!("payengine_{}_fees.wal", std::process::id()));
+
+    let mut bank = Bank::new();
+    bank.set_fees(schedule());
+    bank.set_wal(Wal::open(&path, 1).unwrap());
+    assert!(bank.process_transactions(test_file!("fees.csv")).is_ok());
+
+    let mut replayed = Bank::new();
+    replayed.set_fees(schedule());
+    assert!(replayed.replay_wal(&path).is_ok());
+    fs::remove_file(&path).unwrap();
+    assert_eq!(replayed.to_string(), bank.to_string());
+    assert_eq!(replayed.fee_log(), bank.fee_log());
+
+    for shards in 2..=3 {
+        let mut sharded = Bank::new();
+        sharded.set_fees(schedule());
+        let mut sharded = ShardedBank::new(sharded, shards).unwrap();
+        assert!(sharded.process_transactions(test_file!("fees.csv")).is_ok());
+        let sharded = sharded.into_bank().unwrap();
+        assert_eq!(sharded.to_string(), bank.to_string());
+
+        // Each thread keeps its own ledger, in order for its clients.
+        let mut fees = sharded.fee_log().to_vec();
+        let mut expected = bank.fee_log().to_vec();
+        fees.sort_by_key(|entry| entry.client);
+        expected.sort_by_key(|entry| entry.client);
+        assert_eq!(fees, expected);
+    }
+}
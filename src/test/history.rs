@@ -0,0 +1,151 @@
+use super::amount;
+use crate::bank::Bank;
+use crate::history::HistoryEvent;
+use crate::parallel::ShardedBank;
+use crate::report::OutputFormat;
+use crate::transaction::Transaction;
+use std::fs;
+
+/// Every transaction for a client is in their history in order, along
+/// with why rejected ones were rejected and the balances after each
+#[test]
+fn history() {
+    let mut bank = Bank::new();
+    bank.keep_history();
+    assert!(bank.process_transactions(test_file!("rejects.csv")).is_ok());
+
+    let statuses: Vec<(u32, &str, &str)> = bank
+        .history(1)
+        .iter()
+        .map(|event| (event.tx, event.r#type.as_str(), event.status()))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            (1, "deposit", "accepted"),
+            (2, "withdrawal", "insufficient_funds"),
+            (9, "dispute", "unknown_transaction"),
+            (1, "deposit", "duplicate_transaction"),
+        ]
+    );
+    assert!(bank
+        .history(1)
+        .iter()
+        .all(|event| event.available == amount("1.0") && event.total() == Ok(amount("1.0"))));
+    assert!(bank.history(2).is_empty());
+}
+
+/// Transfers, and disputes of them, are in the history of both clients
+#[test]
+fn history_transfer() {
+    let mut bank = Bank::new();
+    bank.keep_history();
+    assert!(bank
+        .process_transactions(test_file!("transfer_dispute.csv"))
+        .is_ok());
+
+    let destination: Vec<HistoryEvent> = bank.history(2).to_vec();
+    assert_eq!(destination.len(), 6);
+    assert!(destination.iter().all(|event| event.client == 2));
+    assert_eq!(destination[0].r#type, "transfer");
+    assert_eq!(destination[0].available, amount("2.0"));
+    assert_eq!(destination[1].rejected.as_deref(), Some("client_mismatch"));
+    assert_eq!(destination[2].held, amount("2.0"));
+    assert_eq!(destination[5].total(), Ok(amount("0.0")));
+    assert_eq!(bank.history(1).len(), 6);
+
+    // Sharded processing moves the history along with the account, so
+    // it's in the same order.
+    for shards in 2..=3 {
+        let mut front = Bank::new();
+        front.keep_history();
+        let mut sharded = ShardedBank::new(front, shards).unwrap();
+        assert!(sharded
+            .process_transactions(test_file!("transfer_dispute.csv"))
+            .is_ok());
+        let sharded = sharded.into_bank().unwrap();
+        assert_eq!(sharded.history(1), bank.history(1));
+        assert_eq!(sharded.history(2), bank.history(2));
+    }
+
+    // Histories are kept in snapshots.
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    restored.keep_history();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    assert_eq!(restored.history(1), bank.history(1));
+    assert_eq!(restored.history(2), bank.history(2));
+}
+
+/// Histories are only kept when asked for, from then on, or when a
+/// snapshot with them is loaded
+#[test]
+fn history_opt_in() {
+    let mut bank = Bank::new();
+    assert!(bank.process_transactions(test_file!("rejects.csv")).is_ok());
+    assert!(bank.history(1).is_empty());
+
+    bank.keep_history();
+    let deposit = Transaction::new("deposit", 1, 3, Some(amount("1.0")));
+    assert!(bank.handle_transaction(deposit).is_ok());
+    assert_eq!(bank.history(1).len(), 1);
+    assert_eq!(bank.history(1)[0].available, amount("2.0"));
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    assert_eq!(restored.history(1), bank.history(1));
+
+    // They are kept in the snapshots saved again after loading, and
+    // from then on.
+    let mut saved = vec![];
+    assert!(restored.write_snapshot(&mut saved).is_ok());
+    let mut reloaded = Bank::new();
+    assert!(reloaded.read_snapshot(saved.as_slice()).is_ok());
+    assert_eq!(reloaded.history(1), bank.history(1));
+    let deposit = Transaction::new("deposit", 1, 4, Some(amount("1.0")));
+    assert!(reloaded.handle_transaction(deposit).is_ok());
+    assert_eq!(reloaded.history(1).len(), 2);
+
+    let mut snapshot = vec![];
+    assert!(Bank::new().write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    let deposit = Transaction::new("deposit", 1, 1, Some(amount("1.0")));
+    assert!(restored.handle_transaction(deposit).is_ok());
+    assert!(restored.history(1).is_empty());
+
+    // Sharded processing only keeps them if the bank it starts from does.
+    let mut sharded = ShardedBank::new(Bank::new(), 2).unwrap();
+    assert!(sharded
+        .process_transactions(test_file!("transfer_dispute.csv"))
+        .is_ok());
+    assert!(sharded.into_bank().unwrap().history(2).is_empty());
+}
+
+/// Statements list a client's history with running balances, in CSV
+/// or JSON
+#[test]
+fn golden_statement() {
+    let mut bank = Bank::new();
+    bank.keep_history();
+    assert!(bank
+        .process_transactions(test_file!("transfer_dispute.csv"))
+        .is_ok());
+
+    let expected = fs::read_to_string(golden_file!("statement.csv")).unwrap();
+    assert_eq!(format!("{}", bank.statement(1)), expected);
+
+    let expected = fs::read_to_string(golden_file!("statement.json")).unwrap();
+    let statement = bank.statement(2).format(OutputFormat::Json);
+    assert_eq!(format!("{}", statement), expected);
+
+    let statement = bank.statement(2).format(OutputFormat::Ndjson);
+    assert_eq!(format!("{}", statement).lines().count(), 6);
+    assert_eq!(
+        format!("{}", bank.statement(3)),
+        "tx, type, amount, status, available, held, total\n"
+    );
+}
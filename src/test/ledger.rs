@@ -0,0 +1,229 @@
+use super::amount;
+use crate::account::Account;
+use crate::bank::Bank;
+use crate::fees::FeeSchedule;
+use crate::ledger::{Ledger, LedgerAccount, Posting};
+use crate::parallel::ShardedBank;
+use crate::policy::Policy;
+use crate::transaction::Transaction;
+
+/// Returns a posting of the ledger.
+fn posting(tx: u32, from: LedgerAccount, to: LedgerAccount, value: &str) -> Posting {
+    Posting {
+        tx: Some(tx),
+        from,
+        to,
+        amount: amount(value),
+    }
+}
+
+/// Every movement of funds is posted between the ledger accounts it
+/// moves between, and rejected transactions post nothing
+#[test]
+fn ledger_postings() {
+    use LedgerAccount::*;
+
+    let mut bank = Bank::with_policy(Policy {
+        dispute_withdrawals: true,
+        ..Default::default()
+    });
+    bank.keep_ledger();
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("5.0"))),
+        Transaction::new("withdrawal", 1, 2, Some(amount("1.0"))),
+        Transaction::new("withdrawal", 1, 3, Some(amount("100.0"))),
+        Transaction::transfer(1, 2, 4, amount("1.5")),
+        Transaction::authorize(1, 5, amount("0.5"), None),
+        Transaction::new("capture", 1, 5, None),
+        Transaction::new("dispute", 1, 1, Some(amount("2.0"))),
+        Transaction::new("resolve", 1, 1, Some(amount("0.5"))),
+        Transaction::new("dispute", 1, 2, None),
+        Transaction::new("resolve", 1, 2, None),
+        Transaction::new("chargeback", 1, 1, None),
+    ] {
+        let _ = bank.handle_transaction(transaction);
+    }
+
+    assert_eq!(
+        bank.ledger().unwrap().postings(),
+        &[
+            posting(1, Cash, Available(1), "5.0"),
+            posting(2, Available(1), Cash, "1.0"),
+            posting(4, Available(1), Available(2), "1.5"),
+            posting(5, Available(1), Authorized(1), "0.5"),
+            posting(5, Authorized(1), Cash, "0.5"),
+            posting(1, Available(1), Held(1), "2.0"),
+            posting(1, Held(1), Available(1), "0.5"),
+            posting(2, Chargebacks, Held(1), "1.0"),
+            posting(2, Held(1), Chargebacks, "1.0"),
+            posting(1, Held(1), Chargebacks, "1.5"),
+        ]
+    );
+    assert!(bank.verify_ledger().is_ok());
+
+    let balances = bank.ledger().unwrap().balances().unwrap();
+    assert_eq!(balances[&Cash], amount("-3.5"));
+    assert_eq!(balances[&Chargebacks], amount("1.5"));
+    assert_eq!(balances[&Available(1)], amount("0.5"));
+    assert_eq!(balances[&Held(1)], amount("0.0"));
+    assert_eq!(
+        bank.ledger().unwrap().balance(Available(2)).unwrap(),
+        amount("1.5")
+    );
+}
+
+/// The ledger matches the accounts after every kind of input, with
+/// fees, on several threads and after a snapshot
+#[test]
+fn ledger_verify() {
+    let files = [
+        "admin.csv",
+        "authorize.csv",
+        "chargeback_dispute_withdrawal.csv",
+        "fees.csv",
+        "partial_dispute.csv",
+        "processing.csv",
+        "transfer_dispute.csv",
+    ];
+    let policy = Policy {
+        dispute_withdrawals: true,
+        allow_admin: true,
+        ..Default::default()
+    };
+    let fees = || FeeSchedule::load(test_file!("fee_schedule.csv")).unwrap();
+
+    let mut bank = Bank::with_policy(policy.clone());
+    bank.keep_ledger();
+    bank.set_fees(fees());
+    for file in files {
+        assert!(bank.process_transactions(test_file!(file)).is_ok());
+    }
+    assert!(bank.verify_ledger().is_ok());
+
+    let mut sharded = Bank::with_policy(policy);
+    sharded.keep_ledger();
+    sharded.set_fees(fees());
+    let mut sharded = ShardedBank::new(sharded, 3).unwrap();
+    for file in files {
+        assert!(sharded.process_transactions(test_file!(file)).is_ok());
+    }
+    let sharded = sharded.into_bank().unwrap();
+    assert!(sharded.verify_ledger().is_ok());
+    assert_eq!(
+        sharded.ledger().unwrap().len(),
+        bank.ledger().unwrap().len()
+    );
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    restored.keep_ledger();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    assert_eq!(
+        restored.ledger().unwrap().postings(),
+        bank.ledger().unwrap().postings()
+    );
+    assert!(restored.verify_ledger().is_ok());
+
+    // A snapshot with a ledger keeps it, even in a bank that wasn't
+    // asked to, so saving it again doesn't lose the journal.
+    let mut restored = Bank::new();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    let mut saved = vec![];
+    assert!(restored.write_snapshot(&mut saved).is_ok());
+    let mut reloaded = Bank::new();
+    assert!(reloaded.read_snapshot(saved.as_slice()).is_ok());
+    assert_eq!(
+        reloaded.ledger().unwrap().postings(),
+        bank.ledger().unwrap().postings()
+    );
+    assert!(reloaded.verify_ledger().is_ok());
+}
+
+/// Snapshots from before the ledger are brought in as opening balances
+#[test]
+fn ledger_opening() {
+    let snapshot = r#"{"version":1,"accounts":[
+        {"client":1,"available":"1.5","held":"0.5","authorized":"1.0","locked":false},
+        {"client":2,"available":"-2.0","held":"0.0","locked":true}
+    ],"transactions":[]}"#;
+    let mut bank = Bank::new();
+    bank.keep_ledger();
+    assert!(bank.read_snapshot(snapshot.as_bytes()).is_ok());
+    assert_eq!(bank.ledger().unwrap().len(), 4);
+    assert!(bank
+        .ledger()
+        .unwrap()
+        .postings()
+        .iter()
+        .all(|posting| posting.tx.is_none() && posting.from == LedgerAccount::Opening));
+    assert_eq!(
+        bank.ledger()
+            .unwrap()
+            .balance(LedgerAccount::Opening)
+            .unwrap(),
+        amount("-1.0")
+    );
+    assert!(bank.verify_ledger().is_ok());
+}
+
+/// No ledger is kept unless asked for, and one kept later starts from
+/// the balances the accounts have
+#[test]
+fn ledger_opt_in() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .process_transactions(test_file!("processing.csv"))
+        .is_ok());
+    assert!(bank.ledger().is_none());
+    assert!(bank.verify_ledger().is_err());
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let written: serde_json::Value = serde_json::from_slice(&snapshot).unwrap();
+    assert!(written.get("ledger").is_none());
+
+    bank.keep_ledger();
+    assert!(bank
+        .ledger()
+        .unwrap()
+        .postings()
+        .iter()
+        .all(|posting| posting.from == LedgerAccount::Opening));
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 100, Some(amount("1.0"))))
+        .is_ok());
+    assert!(bank.verify_ledger().is_ok());
+}
+
+/// Verifying fails when an account doesn't hold what the ledger says,
+/// or the ledger has funds for an account that doesn't exist
+#[test]
+fn ledger_mismatch() {
+    let mut ledger = Ledger::default();
+    ledger.post(
+        Some(1),
+        LedgerAccount::Cash,
+        LedgerAccount::Available(1),
+        amount("2.0"),
+    );
+
+    let mut account = Account::new(1);
+    account.available = amount("2.0");
+    assert!(ledger.verify([&account]).is_ok());
+    account.available = amount("2.5");
+    assert!(ledger.verify([&account]).is_err());
+    account.available = amount("2.0");
+    account.held = amount("0.1");
+    assert!(ledger.verify([&account]).is_err());
+    assert!(ledger.verify([]).is_err());
+
+    // Zero amounts aren't posted.
+    ledger.post(
+        Some(2),
+        LedgerAccount::Cash,
+        LedgerAccount::Held(3),
+        amount("0.0"),
+    );
+    assert_eq!(ledger.len(), 1);
+}
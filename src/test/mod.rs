@@ -1,2 +1,45 @@
+use crate::amount::Amount;
+use std::str::FromStr;
+
+/// Returns the path of a file in the src/test/csv/ directory.
+macro_rules! test_file {
+    ($file:expr) => {
+        // PathBuf from_str is infallible
+        <std::path::PathBuf as std::str::FromStr>::from_str(env!("CARGO_MANIFEST_DIR"))
+            .unwrap()
+            .join("src/test/csv/")
+            .join($file)
+    };
+}
+
+/// Returns the path of a file in the src/test/golden/ directory.
+macro_rules! golden_file {
+    ($file:expr) => {
+        <std::path::PathBuf as std::str::FromStr>::from_str(env!("CARGO_MANIFEST_DIR"))
+            .unwrap()
+            .join("src/test/golden/")
+            .join($file)
+    };
+}
+
 mod account;
+mod amount;
+mod bank;
 mod csv;
+mod decoder;
+mod fees;
+mod history;
+mod ledger;
+mod parallel;
+mod query;
+mod rejects;
+mod report;
+mod server;
+mod snapshot;
+mod store;
+mod wal;
+
+/// Parses an Amount for comparisons in tests.
+fn amount(amount: &str) -> Amount {
+    Amount::from_str(amount).unwrap()
+}
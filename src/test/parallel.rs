@@ -0,0 +1,110 @@
+use crate::audit::AuditEntry;
+use crate::bank::Bank;
+use crate::parallel::ShardedBank;
+use crate::policy::Policy;
+use crate::rejects::{RejectsFormat, RejectsWriter};
+use std::fs;
+
+/// Processes files into a bank, sharded if `shards` is given, and
+/// returns the report, the rejects written and the audit log.
+fn process(
+    files: &[&str],
+    policy: Policy,
+    shards: Option<usize>,
+) -> (String, String, Vec<AuditEntry>) {
+    let name = format!("parallel_{}_{:?}.csv", files.join("_"), shards);
+    let path = std::env::temp_dir().join(format!("payengine_{}_{}", std::process::id(), name));
+
+    let mut bank = Bank::with_policy(policy);
+    bank.set_rejects(RejectsWriter::create(&path, RejectsFormat::Csv).unwrap());
+    let bank = match shards {
+        Some(shards) => {
+            let mut sharded = ShardedBank::new(bank, shards).unwrap();
+            for file in files {
+                assert!(sharded.process_transactions(test_file!(file)).is_ok());
+            }
+            sharded.into_bank().unwrap()
+        }
+        None => {
+            for file in files {
+                assert!(bank.process_transactions(test_file!(file)).is_ok());
+            }
+            bank
+        }
+    };
+
+    let rejects = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    (bank.to_string(), rejects, bank.audit_log().to_vec())
+}
+
+/// Sharded processing gives the same accounts, rejections and audit log
+/// as processing sequentially, for any number of shards
+#[test]
+fn sharded_matches_sequential() {
+    for files in [
+        &["cross_client.csv"][..],
+        &["processing.csv"],
+        &["transfer.csv", "transfer_dispute.csv"],
+        &["authorize.csv", "deposit.csv"],
+        &["admin.csv"],
+        &["locked.csv", "rejects.csv", "sorting.csv"],
+        &["dispute_window.csv"],
+    ] {
+        for policy in [
+            Policy::default(),
+            Policy {
+                dispute_withdrawals: true,
+                allow_admin: true,
+                ..Default::default()
+            },
+            Policy {
+                dispute_window: Some(1000),
+                ..Default::default()
+            },
+        ] {
+            let expected = process(files, policy.clone(), None);
+            for shards in 1..=4 {
+                assert_eq!(
+                    process(files, policy.clone(), Some(shards)),
+                    expected,
+                    "{:?} with {} shards",
+                    files,
+                    shards
+                );
+            }
+        }
+    }
+}
+
+/// A sharded bank continues from the state it was created from
+#[test]
+fn sharded_continues() {
+    let mut sequential = Bank::new();
+    assert!(sequential
+        .process_transactions(test_file!("cross_client.csv"))
+        .is_ok());
+    assert!(sequential
+        .process_transactions(test_file!("sorting.csv"))
+        .is_ok());
+
+    let mut bank = Bank::new();
+    assert!(bank
+        .process_transactions(test_file!("cross_client.csv"))
+        .is_ok());
+    let mut sharded = ShardedBank::new(bank, 3).unwrap();
+    assert!(sharded
+        .process_transactions(test_file!("sorting.csv"))
+        .is_ok());
+    let bank = sharded.into_bank().unwrap();
+
+    assert_eq!(bank.to_string(), sequential.to_string());
+    assert_eq!(bank.num_logs(), sequential.num_logs());
+}
+
+/// The number of shards is bounded
+#[test]
+fn sharded_bounds() {
+    assert!(ShardedBank::new(Bank::new(), 0).is_err());
+    assert!(ShardedBank::new(Bank::new(), 65).is_err());
+}
@@ -0,0 +1,131 @@
+use super::amount;
+use crate::bank::Bank;
+use crate::decoder::{Decoder, InputFormat};
+use crate::parallel::ShardedBank;
+use crate::query::{AsOf, Cutoff};
+use crate::report::{SortKey, SortOrder};
+use std::fs::File;
+
+/// Opens a decoder over a test file.
+fn decoder(file: &str) -> Box<dyn Decoder> {
+    let path = test_file!(file);
+    InputFormat::from_path(&path)
+        .decoder(File::open(&path).unwrap())
+        .unwrap()
+}
+
+/// Processes test files into a bank up to a point, returning the bank
+/// and the cutoff.
+fn process_until(files: &[&str], as_of: AsOf) -> (Bank, Cutoff) {
+    let mut bank = Bank::new();
+    let mut cutoff = Cutoff::new(as_of);
+    for file in files {
+        assert!(bank
+            .process_decoder(file, cutoff.decoder(file, decoder(file)))
+            .is_ok());
+    }
+    (bank, cutoff)
+}
+
+/// A point at a line of a test file.
+fn line(input: &str, line: u64) -> AsOf {
+    AsOf::Line {
+        input: input.to_string(),
+        line,
+    }
+}
+
+/// The state as of a line is the state after handling every row of the
+/// inputs up to it, with lines numbered the way rejections are and
+/// including rows that fail to parse
+#[test]
+fn as_of_line() {
+    let files = ["processing.csv", "rejects.csv"];
+    let available = |as_of| {
+        let (bank, cutoff) = process_until(&files, as_of);
+        assert!(cutoff.reached());
+        bank.get_account(1).map(|account| account.available)
+    };
+
+    // Line 1 is the header, and line 2 the first row.
+    assert_eq!(available(line("processing.csv", 1)), None);
+    assert_eq!(available(line("processing.csv", 2)), Some(amount("2.0")));
+    assert_eq!(available(line("processing.csv", 3)), Some(amount("1.0")));
+    assert_eq!(available(line("processing.csv", 5)), Some(amount("1.0")));
+    assert_eq!(available(line("rejects.csv", 1)), Some(amount("1.0")));
+
+    // Line 3 of the second input fails to parse.
+    let (_, cutoff) = process_until(&files, line("rejects.csv", 3));
+    assert!(cutoff.reached());
+    assert_eq!(cutoff.rows(), 9);
+
+    // A line past the end of an input stops after its last row.
+    let (_, cutoff) = process_until(&files, line("processing.csv", 99));
+    assert!(cutoff.reached());
+    assert_eq!(cutoff.rows(), 7);
+
+    let (_, cutoff) = process_until(&files, line("missing.csv", 2));
+    assert!(!cutoff.reached());
+    assert!(cutoff.check().is_err());
+}
+
+/// A client's balances as of a point can be queried from the bank
+#[test]
+fn balance_as_of() {
+    let inputs = || [("processing.csv", decoder("processing.csv"))];
+
+    let mut bank = Bank::new();
+    let account = bank.balance_as_of(1, line("processing.csv", 5), inputs());
+    let account = account.unwrap().unwrap();
+    assert_eq!(account.available, amount("1.0"));
+    assert_eq!(account.held, amount("1.0"));
+    assert!(bank
+        .balance_as_of(2, AsOf::Tx(3), inputs())
+        .unwrap()
+        .is_none());
+
+    let mut bank = Bank::new();
+    assert!(bank.balance_as_of(1, AsOf::Tx(99), inputs()).is_err());
+}
+
+/// The state as of a tx ID is the state after the first row with it,
+/// and is the same when processed on several threads
+#[test]
+fn as_of_tx() {
+    let (bank, cutoff) = process_until(&["processing.csv"], AsOf::Tx(3));
+    assert!(cutoff.reached());
+    let account = bank.get_account(1).unwrap();
+    assert_eq!(account.available, amount("2.0"));
+    assert_eq!(account.held, amount("0.0"));
+    assert!(!account.is_locked());
+
+    let mut cutoff = Cutoff::new(AsOf::Tx(3));
+    let mut sharded = ShardedBank::new(Bank::new(), 2).unwrap();
+    assert!(sharded
+        .process_decoder(
+            "processing.csv",
+            cutoff.decoder("processing.csv", decoder("processing.csv"))
+        )
+        .is_ok());
+    assert!(cutoff.reached());
+    assert_eq!(cutoff.rows(), 3);
+    assert_eq!(sharded.into_bank().unwrap().to_string(), bank.to_string());
+
+    let (_, cutoff) = process_until(&["processing.csv"], AsOf::Tx(99));
+    assert!(!cutoff.reached());
+}
+
+/// A report can be narrowed down to a single client's account
+#[test]
+fn report_only() {
+    let mut bank = Bank::new();
+    assert!(bank.process_transactions(test_file!("sorting.csv")).is_ok());
+
+    let report = bank.report(SortKey::Client, SortOrder::Ascending).only(2);
+    let output = report.to_string();
+    assert_eq!(output.lines().count(), 2);
+    assert!(output.lines().nth(1).unwrap().starts_with("2, "));
+
+    let report = bank.report(SortKey::Client, SortOrder::Ascending).only(999);
+    assert_eq!(report.to_string().lines().count(), 1);
+}
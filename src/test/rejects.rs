@@ -0,0 +1,98 @@
+use crate::bank::Bank;
+use crate::rejects::{RejectsFormat, RejectsWriter};
+use std::fs;
+use std::str::FromStr;
+
+/// Processes rejects.csv, writing rejections to a temporary file
+/// in `format`, and returns what was written.
+fn process_rejects(name: &str, format: RejectsFormat) -> String {
+    let path = std::env::temp_dir().join(format!("payengine_{}_{}", std::process::id(), name));
+
+    let mut bank = Bank::new();
+    bank.set_rejects(RejectsWriter::create(&path, format).unwrap());
+    assert!(bank.process_transactions(test_file!("rejects.csv")).is_ok());
+
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    written
+}
+
+/// Rejected rows are written as CSV with line numbers and reasons
+#[test]
+fn rejects_csv() {
+    let written = process_rejects("rejects.csv", RejectsFormat::Csv);
+    let lines: Vec<&str> = written.lines().collect();
+
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "source,line,reason,record,message");
+    assert!(lines[1].contains("rejects.csv,3,parse_error,\"deposit,a,b,c\","));
+    assert!(lines[2].contains("rejects.csv,4,insufficient_funds,\"withdrawal,1,2,5.0\","));
+    assert!(lines[3].contains("rejects.csv,5,unknown_transaction,\"dispute,1,9\","));
+    assert!(lines[4].contains("rejects.csv,6,duplicate_transaction,\"deposit,1,1,1.0\","));
+}
+
+/// Rejected rows are written as NDJSON
+#[test]
+fn rejects_ndjson() {
+    let written = process_rejects("rejects.ndjson", RejectsFormat::Ndjson);
+    let rows: Vec<serde_json::Value> = written
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(rows.len(), 4);
+    assert!(rows[1]["source"].as_str().unwrap().ends_with("rejects.csv"));
+    assert_eq!(rows[1]["line"], 4);
+    assert_eq!(rows[1]["reason"], "insufficient_funds");
+    assert_eq!(rows[1]["record"], "withdrawal,1,2,5.0");
+    assert_eq!(
+        rows[1]["message"],
+        "Insufficient funds in account 1: has 1.0000 wants 5.0000"
+    );
+}
+
+/// Formats are guessed from extensions
+#[test]
+fn rejects_format() {
+    assert_eq!(RejectsFormat::from_path("out.jsonl"), RejectsFormat::Ndjson);
+    assert_eq!(
+        RejectsFormat::from_path("out.ndjson"),
+        RejectsFormat::Ndjson
+    );
+    assert_eq!(RejectsFormat::from_path("out.csv"), RejectsFormat::Csv);
+    assert_eq!(RejectsFormat::from_path("out"), RejectsFormat::Csv);
+    assert!(RejectsFormat::from_str("xml").is_err());
+}
+
+/// Rejections name the input they came from
+#[test]
+fn rejects_source() {
+    let path = std::env::temp_dir().join(format!("payengine_{}_source.csv", std::process::id()));
+
+    let mut bank = Bank::new();
+    bank.set_rejects(RejectsWriter::create(&path, RejectsFormat::Csv).unwrap());
+    let input = "type, client, tx, amount\nwithdrawal, 1, 1, 1.0";
+    assert!(bank.process_reader("stdin", input.as_bytes()).is_ok());
+
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(written.lines().nth(1).unwrap().starts_with("stdin,2,"));
+}
+
+/// Rows that aren't valid UTF-8 are rejected with their raw text
+#[test]
+fn rejects_malformed() {
+    let path = std::env::temp_dir().join(format!("payengine_{}_malformed.csv", std::process::id()));
+
+    let mut bank = Bank::new();
+    bank.set_rejects(RejectsWriter::create(&path, RejectsFormat::Csv).unwrap());
+    let input = b"type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, \xff1.0\n";
+    assert!(bank.process_reader("stdin", &input[..]).is_ok());
+
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let row = written.lines().nth(1).unwrap();
+    assert!(row.starts_with("stdin,3,parse_error,"), "{}", row);
+    assert!(row.contains("deposit,1,2,\u{fffd}1.0"), "{}", row);
+    assert_eq!(written.lines().count(), 2);
+}
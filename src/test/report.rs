@@ -0,0 +1,85 @@
+use crate::bank::Bank;
+use crate::report::{OutputFormat, SortKey, SortOrder};
+use std::fs;
+
+/// Processes sorting.csv into a new bank.
+fn process_sorting() -> Bank {
+    let mut bank = Bank::new();
+    assert!(bank.process_transactions(test_file!("sorting.csv")).is_ok());
+    bank
+}
+
+/// Returns the client IDs of a report in order.
+fn clients(bank: &Bank, key: SortKey, order: SortOrder) -> Vec<u16> {
+    bank.sorted_accounts(key, order)
+        .iter()
+        .map(|account| account.client())
+        .collect()
+}
+
+/// The default output is ordered by client ID and byte-stable
+#[test]
+fn golden() {
+    let bank = process_sorting();
+    let expected = fs::read_to_string(golden_file!("sorting.csv")).unwrap();
+
+    assert_eq!(format!("{}", bank), expected);
+    assert_eq!(
+        format!("{}", bank.report(SortKey::Client, SortOrder::Ascending)),
+        expected
+    );
+}
+
+/// Sorting by each key, with ties broken by ascending client ID
+#[test]
+fn sorted() {
+    let bank = process_sorting();
+
+    assert_eq!(
+        clients(&bank, SortKey::Client, SortOrder::Descending),
+        [12, 8, 5, 3, 2, 1]
+    );
+    assert_eq!(
+        clients(&bank, SortKey::Total, SortOrder::Ascending),
+        [2, 3, 12, 1, 5, 8]
+    );
+    assert_eq!(
+        clients(&bank, SortKey::Total, SortOrder::Descending),
+        [8, 1, 5, 12, 2, 3]
+    );
+    assert_eq!(
+        clients(&bank, SortKey::Available, SortOrder::Descending),
+        [1, 5, 12, 3, 8, 2]
+    );
+    assert_eq!(
+        clients(&bank, SortKey::Held, SortOrder::Ascending),
+        [1, 3, 5, 12, 2, 8]
+    );
+}
+
+/// JSON output is an array of snapshots with exact decimal strings
+#[test]
+fn golden_json() {
+    let bank = process_sorting();
+    let report = bank.report(SortKey::Client, SortOrder::Ascending);
+    let expected = fs::read_to_string(golden_file!("sorting.json")).unwrap();
+
+    assert_eq!(format!("{}", report.format(OutputFormat::Json)), expected);
+}
+
+/// NDJSON output is one snapshot per line, sorted the same as CSV
+#[test]
+fn golden_ndjson() {
+    let bank = process_sorting();
+    let report = bank.report(SortKey::Client, SortOrder::Ascending);
+    let expected = fs::read_to_string(golden_file!("sorting.ndjson")).unwrap();
+
+    assert_eq!(format!("{}", report.format(OutputFormat::Ndjson)), expected);
+
+    let report = bank.report(SortKey::Total, SortOrder::Descending);
+    let output = format!("{}", report.format(OutputFormat::Ndjson));
+    let first: serde_json::Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+
+    assert_eq!(first["client"], 8);
+    assert_eq!(first["held"], "4.5000");
+}
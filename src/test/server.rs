@@ -0,0 +1,124 @@
+use crate::bank::Bank;
+use crate::server::Server;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+/// Starts a server for a fresh bank on a free local port.
+fn serve() -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0", Bank::new()).unwrap();
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    address
+}
+
+/// A connection to a server, sending one request at a time.
+struct Client {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    fn connect(address: SocketAddr) -> Self {
+        let writer = TcpStream::connect(address).unwrap();
+        let reader = BufReader::new(writer.try_clone().unwrap());
+        Self { writer, reader }
+    }
+
+    /// Sends a request and returns the reply.
+    fn request(&mut self, request: &str) -> Value {
+        writeln!(self.writer, "{}", request).unwrap();
+        let mut reply = String::new();
+        self.reader.read_line(&mut reply).unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+}
+
+/// Transactions are accepted or rejected as CSV rows or JSON objects
+#[test]
+fn server_transactions() {
+    let mut client = Client::connect(serve());
+
+    let reply = client.request("deposit, 1, 1, 1.5");
+    assert_eq!(reply["status"], "accepted");
+    assert_eq!(reply["tx"], 1);
+
+    let reply = client.request(r#"{"type": "withdrawal", "client": 1, "tx": 2, "amount": "5.0"}"#);
+    assert_eq!(reply["status"], "rejected");
+    assert_eq!(reply["tx"], 2);
+    assert_eq!(reply["reason"], "insufficient_funds");
+
+    let reply = client.request(r#"{"type": "dispute", "client": 1, "tx": 1}"#);
+    assert_eq!(reply["status"], "accepted");
+
+    let reply = client.request("destroy, 1, 3, 1.0");
+    assert_eq!(reply["status"], "rejected");
+    assert_eq!(reply["reason"], "unsupported_type");
+
+    let reply = client.request("deposit, one, 4, 1.0");
+    assert_eq!(reply["status"], "error");
+    assert_eq!(reply["reason"], "parse_error");
+}
+
+/// Accounts can be queried one at a time or all together
+#[test]
+fn server_accounts() {
+    let mut client = Client::connect(serve());
+
+    client.request("deposit, 2, 1, 2.0");
+    client.request("deposit, 1, 2, 1.0");
+    client.request("dispute, 1, 2,");
+
+    let reply = client.request("account 1");
+    assert_eq!(reply["status"], "ok");
+    assert_eq!(reply["account"]["held"], "1.0000");
+    assert_eq!(reply["account"]["total"], "1.0000");
+
+    let reply = client.request("account 3");
+    assert_eq!(reply["status"], "error");
+    assert_eq!(reply["reason"], "unknown_account");
+
+    let reply = client.request("account x");
+    assert_eq!(reply["reason"], "parse_error");
+
+    let reply = client.request("accounts");
+    let accounts = reply["accounts"].as_array().unwrap();
+    assert_eq!(accounts.len(), 2);
+    assert_eq!(accounts[0]["client"], 1);
+    assert_eq!(accounts[1]["available"], "2.0000");
+}
+
+/// Connections share the bank and are served concurrently
+#[test]
+fn server_concurrent() {
+    let address = serve();
+
+    // Holding a connection open doesn't stop another from being served.
+    let mut idle = Client::connect(address);
+    let handles: Vec<_> = (0..4u32)
+        .map(|client| {
+            thread::spawn(move || {
+                let mut connection = Client::connect(address);
+                for tx in 0..25 {
+                    let request = format!("deposit, {}, {}, 1.0", client, client * 100 + tx);
+                    assert_eq!(connection.request(&request)["status"], "accepted");
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let reply = idle.request("accounts");
+    let accounts = reply["accounts"].as_array().unwrap();
+    assert_eq!(accounts.len(), 4);
+    assert!(accounts
+        .iter()
+        .all(|account| account["available"] == "25.0000"));
+
+    // A tx ID is unique across connections.
+    let reply = idle.request("deposit, 9, 100, 1.0");
+    assert_eq!(reply["reason"], "duplicate_transaction");
+}
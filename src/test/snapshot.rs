@@ -0,0 +1,255 @@
+use super::amount;
+use crate::account::AccountStatus;
+use crate::bank::Bank;
+use crate::error::EngineError;
+use crate::fees::FeeSchedule;
+use crate::policy::Policy;
+use crate::snapshot::SNAPSHOT_VERSION;
+use crate::transaction::{DisputeState, HoldState, Transaction};
+use std::fs;
+
+/// Returns a temporary snapshot path unique to this process.
+fn snapshot_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("payengine_{}_{}", std::process::id(), name))
+}
+
+/// A restored bank continues where the saved one left off
+#[test]
+fn snapshot_roundtrip() {
+    let path = snapshot_path("roundtrip.json");
+
+    let mut bank = Bank::new();
+    assert!(bank.process_transactions(test_file!("locked.csv")).is_ok());
+    assert!(bank.save_snapshot(&path).is_ok());
+
+    let mut restored = Bank::new();
+    assert!(restored.load_snapshot(&path).is_ok());
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(restored.to_string(), bank.to_string());
+    assert_eq!(restored.num_logs(), bank.num_logs());
+    assert_eq!(
+        restored.get_logged_transaction(1).unwrap().state,
+        DisputeState::ChargedBack
+    );
+
+    // Disputes can reference transactions from before the snapshot.
+    assert!(restored
+        .handle_transaction(Transaction::new("deposit", 2, 10, Some(amount("3.0"))))
+        .is_ok());
+    assert!(restored
+        .handle_transaction(Transaction::new("dispute", 2, 10, None))
+        .is_ok());
+    assert_eq!(restored.get_account(2).unwrap().held, amount("3.0"));
+}
+
+/// Transfers and holds are restored, and restored holds still expire
+#[test]
+fn snapshot_holds() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .process_transactions(test_file!("authorize.csv"))
+        .is_ok());
+    assert!(bank
+        .handle_transaction(Transaction::transfer(1, 3, 6, amount("0.5")))
+        .is_ok());
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+
+    assert_eq!(restored.to_string(), bank.to_string());
+    assert_eq!(
+        restored.get_logged_transaction(6).unwrap().destination,
+        Some(3)
+    );
+    assert_eq!(restored.expire_holds(100), Ok(1));
+    assert_eq!(restored.get_account(1).unwrap().available, amount("2.5"));
+    assert_eq!(
+        restored
+            .get_logged_transaction(5)
+            .unwrap()
+            .hold
+            .unwrap()
+            .state,
+        HoldState::Expired
+    );
+}
+
+/// Disputed and resolved portions are restored, and snapshots without
+/// them dispute and resolve transactions as a whole
+#[test]
+fn snapshot_partial_disputes() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .process_transactions(test_file!("partial_dispute.csv"))
+        .is_ok());
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    let tx = restored.get_logged_transaction(1).unwrap();
+    assert_eq!(tx.disputed, amount("2.0"));
+    assert_eq!(tx.charged_back, amount("3.0"));
+
+    let snapshot = r#"{"version":1,"accounts":[],"transactions":[
+        {"tx":1,"client":1,"amount":"2.0","deposit":true,"state":"disputed"},
+        {"tx":2,"client":1,"amount":"1.0","deposit":true,"state":"charged_back"}
+    ]}"#;
+    assert!(restored.read_snapshot(snapshot.as_bytes()).is_ok());
+    let (disputed, charged_back) = (
+        restored.get_logged_transaction(1).unwrap(),
+        restored.get_logged_transaction(2).unwrap(),
+    );
+    assert_eq!(disputed.disputed, amount("2.0"));
+    assert_eq!(disputed.undisputed(), amount("0.0"));
+    assert_eq!(charged_back.disputed, amount("0.0"));
+    assert_eq!(charged_back.charged_back, amount("1.0"));
+
+    let snapshot = r#"{"version":2,"accounts":[],"transactions":[
+        {"tx":1,"client":1,"amount":"2.0","deposit":true,"state":"resolved",
+         "charged_back":"0.5"}
+    ]}"#;
+    assert!(restored.read_snapshot(snapshot.as_bytes()).is_ok());
+    let resolved = restored.get_logged_transaction(1).unwrap();
+    assert_eq!(resolved.resolved, amount("1.5"));
+    assert_eq!(resolved.never_disputed(), amount("0.0"));
+
+    let mut bank = Bank::new();
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("2.0"))),
+        Transaction::new("dispute", 1, 1, Some(amount("0.5"))),
+        Transaction::new("resolve", 1, 1, None),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    assert_eq!(
+        restored.get_logged_transaction(1).unwrap().resolved,
+        amount("0.5")
+    );
+}
+
+/// Account statuses and the audit log are restored, and snapshots
+/// without a status take it from whether the account is locked
+#[test]
+fn snapshot_admin() {
+    let mut bank = Bank::with_policy(Policy {
+        allow_admin: true,
+        ..Default::default()
+    });
+    assert!(bank.process_transactions(test_file!("admin.csv")).is_ok());
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    assert_eq!(restored.audit_log(), bank.audit_log());
+    assert_eq!(
+        restored.get_account(1).unwrap().status,
+        AccountStatus::Frozen
+    );
+    assert_eq!(
+        restored.get_account(2).unwrap().status,
+        AccountStatus::Closed
+    );
+
+    let snapshot = r#"{"version":1,"accounts":[
+        {"client":1,"available":"0.0","held":"0.0","locked":true},
+        {"client":2,"available":"0.0","held":"0.0","locked":false}
+    ],"transactions":[]}"#;
+    assert!(restored.read_snapshot(snapshot.as_bytes()).is_ok());
+    assert_eq!(
+        restored.get_account(1).unwrap().status,
+        AccountStatus::Locked
+    );
+    assert_eq!(
+        restored.get_account(2).unwrap().status,
+        AccountStatus::Active
+    );
+    assert!(restored.audit_log().is_empty());
+}
+
+/// The fee ledger is restored
+#[test]
+fn snapshot_fees() {
+    let mut bank = Bank::new();
+    bank.set_fees(FeeSchedule::load(test_file!("fee_schedule.csv")).unwrap());
+    assert!(bank.process_transactions(test_file!("fees.csv")).is_ok());
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::new();
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    assert_eq!(restored.fee_log(), bank.fee_log());
+    assert_eq!(restored.to_string(), bank.to_string());
+}
+
+/// Timestamps are restored, along with the transactions flagged as out
+/// of order and the latest timestamp seen
+#[test]
+fn snapshot_timestamps() {
+    let policy = Policy {
+        dispute_window: Some(1000),
+        ..Default::default()
+    };
+    let mut bank = Bank::with_policy(policy.clone());
+    assert!(bank
+        .process_transactions(test_file!("dispute_window.csv"))
+        .is_ok());
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let mut restored = Bank::with_policy(policy);
+    assert!(restored.read_snapshot(snapshot.as_slice()).is_ok());
+    assert_eq!(restored.out_of_order(), bank.out_of_order());
+
+    let late = Transaction::new("dispute", 1, 1, None).at(3001);
+    assert!(restored.check_order(&late).is_some());
+    assert_eq!(
+        restored.handle_transaction(late),
+        Err(EngineError::DisputeWindowExpired {
+            tx: 1,
+            window: 1000
+        })
+    );
+}
+
+/// Saving the same state twice gives the same snapshot
+#[test]
+fn snapshot_deterministic() {
+    let mut bank = Bank::new();
+    assert!(bank.process_transactions(test_file!("sorting.csv")).is_ok());
+
+    let (mut first, mut second) = (vec![], vec![]);
+    assert!(bank.write_snapshot(&mut first).is_ok());
+    assert!(bank.write_snapshot(&mut second).is_ok());
+    assert_eq!(first, second);
+}
+
+/// Snapshots of a newer version are refused, and older ones are read
+#[test]
+fn snapshot_version() {
+    let mut bank = Bank::new();
+    let newer = format!(
+        r#"{{"version":{},"accounts":[],"transactions":[]}}"#,
+        SNAPSHOT_VERSION + 1
+    );
+
+    assert!(bank.read_snapshot(newer.as_bytes()).is_err());
+    assert!(bank
+        .read_snapshot(r#"{"version":0,"accounts":[],"transactions":[]}"#.as_bytes())
+        .is_err());
+    assert!(bank
+        .read_snapshot(r#"{"version":1,"accounts":[],"transactions":[]}"#.as_bytes())
+        .is_ok());
+
+    let mut snapshot = vec![];
+    assert!(bank.write_snapshot(&mut snapshot).is_ok());
+    let written: serde_json::Value = serde_json::from_slice(&snapshot).unwrap();
+    assert_eq!(written["version"], SNAPSHOT_VERSION);
+}
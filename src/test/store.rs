@@ -0,0 +1,220 @@
+use super::amount;
+use crate::account::{Account, AccountStatus};
+use crate::bank::Bank;
+use crate::policy::Policy;
+use crate::store::{
+    AccountStore, DenseAccountStore, DiskTransactionStore, MemoryAccountStore,
+    MemoryTransactionStore, TransactionStore,
+};
+use crate::transaction::{DisputeState, Hold, HoldState, LoggedTransaction};
+use std::fs;
+use std::path::PathBuf;
+
+/// Returns a temporary path unique to this process.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("payengine_{}_{}", std::process::id(), name))
+}
+
+/// A logged deposit for client 1.
+fn logged(value: &str, state: DisputeState) -> LoggedTransaction {
+    LoggedTransaction {
+        client: 1,
+        amount: amount(value),
+        deposit: true,
+        destination: None,
+        hold: None,
+        state,
+        disputed: amount("0.0"),
+        resolved: amount("0.0"),
+        charged_back: amount("0.0"),
+        timestamp: None,
+    }
+}
+
+/// Inserts, replaces and removes through a store, checking what it returns
+fn exercise<T: TransactionStore>(mut store: T) {
+    assert!(store.is_empty());
+    assert!(store
+        .insert(7, logged("1.5", DisputeState::Undisputed))
+        .is_ok());
+    assert!(store
+        .insert(u32::MAX, logged("-2.25", DisputeState::ChargedBack))
+        .is_ok());
+    assert!(store
+        .insert(3, logged("0.0001", DisputeState::Disputed))
+        .is_ok());
+    assert!(store
+        .insert(7, logged("1.5", DisputeState::Resolved))
+        .is_ok());
+    assert_eq!(store.len(), 3);
+
+    let found = store.get(7).unwrap().unwrap();
+    assert_eq!(found.amount, amount("1.5"));
+    assert_eq!(found.state, DisputeState::Resolved);
+
+    // Partly disputed transactions keep their portions.
+    let partial = LoggedTransaction {
+        disputed: amount("0.25"),
+        resolved: amount("0.125"),
+        charged_back: amount("1.0"),
+        ..logged("1.5", DisputeState::Disputed)
+    };
+    assert!(store.insert(7, partial).is_ok());
+    let found = store.get(7).unwrap().unwrap();
+    assert_eq!(found.disputed, amount("0.25"));
+    assert_eq!(found.resolved, amount("0.125"));
+    assert_eq!(found.charged_back, amount("1.0"));
+    assert!(store
+        .insert(7, logged("1.5", DisputeState::Resolved))
+        .is_ok());
+    assert_eq!(
+        store.get(u32::MAX).unwrap().unwrap().state,
+        DisputeState::ChargedBack
+    );
+    assert!(store.get(4).unwrap().is_none());
+
+    let txs: Vec<u32> = store.iter().map(|entry| entry.unwrap().0).collect();
+    assert_eq!(txs, vec![3, 7, u32::MAX]);
+
+    // Transfers keep their destination, and authorizations their hold.
+    let hold = Hold {
+        state: HoldState::Released,
+        expires: Some(u64::MAX),
+    };
+    let transfer = LoggedTransaction {
+        deposit: false,
+        destination: Some(u16::MAX),
+        timestamp: Some(u64::MAX),
+        ..logged("2.0", DisputeState::Undisputed)
+    };
+    let authorization = LoggedTransaction {
+        deposit: false,
+        hold: Some(hold),
+        ..logged("3.0", DisputeState::Undisputed)
+    };
+    assert!(store.insert(5, transfer).is_ok());
+    assert!(store.insert(6, authorization).is_ok());
+    assert_eq!(store.get(5).unwrap().unwrap().destination, Some(u16::MAX));
+    assert_eq!(store.get(5).unwrap().unwrap().hold, None);
+    assert_eq!(store.get(5).unwrap().unwrap().timestamp, Some(u64::MAX));
+    assert_eq!(store.get(6).unwrap().unwrap().timestamp, None);
+    assert_eq!(store.get(6).unwrap().unwrap().destination, None);
+    assert_eq!(store.get(6).unwrap().unwrap().hold, Some(hold));
+    assert!(store.remove(5).is_ok());
+    assert!(store.remove(6).is_ok());
+
+    assert!(store.remove(3).is_ok());
+    assert!(store.remove(4).is_ok());
+    assert_eq!(store.len(), 2);
+    assert!(store.get(3).unwrap().is_none());
+
+    assert!(store.clear().is_ok());
+    assert!(store.is_empty());
+    assert_eq!(store.iter().count(), 0);
+}
+
+/// The in-memory store behaves as a map
+#[test]
+fn memory_store() {
+    exercise(MemoryTransactionStore::default());
+}
+
+/// The disk store behaves the same as the in-memory store
+#[test]
+fn disk_store() {
+    let path = temp_path("store.bin");
+    exercise(DiskTransactionStore::create(&path).unwrap());
+    fs::remove_file(&path).unwrap();
+}
+
+/// A bank logging on disk processes the same as one logging in memory
+#[test]
+fn disk_store_bank() {
+    let (path, snapshot) = (
+        temp_path("bank_store.bin"),
+        temp_path("store_snapshot.json"),
+    );
+
+    let mut memory = Bank::new();
+    let store = DiskTransactionStore::create(&path).unwrap();
+    let mut disk = Bank::with_store(store, Policy::default());
+    for file in ["locked.csv", "rejects.csv", "sorting.csv"] {
+        assert!(memory.process_transactions(test_file!(file)).is_ok());
+        assert!(disk.process_transactions(test_file!(file)).is_ok());
+    }
+    assert_eq!(disk.to_string(), memory.to_string());
+    assert_eq!(disk.num_logs(), memory.num_logs());
+
+    // Snapshots don't depend on the store either.
+    let (mut from_memory, mut from_disk) = (vec![], vec![]);
+    assert!(memory.write_snapshot(&mut from_memory).is_ok());
+    assert!(disk.write_snapshot(&mut from_disk).is_ok());
+    assert_eq!(from_disk, from_memory);
+
+    assert!(memory.save_snapshot(&snapshot).is_ok());
+    assert!(disk.load_snapshot(&snapshot).is_ok());
+    assert_eq!(disk.to_string(), memory.to_string());
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&snapshot).unwrap();
+}
+
+/// Creates, replaces and removes accounts through a store
+fn exercise_accounts<A: AccountStore>(mut store: A) {
+    assert!(store.is_empty());
+    assert!(store.get(3).is_none());
+
+    store.get_or_create(3).available = amount("1.5");
+    store.get_or_create(u16::MAX).status = AccountStatus::Locked;
+    assert_eq!(store.get_or_create(3).available, amount("1.5"));
+    assert_eq!(store.len(), 2);
+
+    let mut replaced = Account::new(3);
+    replaced.held = amount("2.0");
+    store.insert(replaced);
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get(3).unwrap().held, amount("2.0"));
+    assert_eq!(store.get(3).unwrap().available, amount("0.0"));
+
+    let mut clients: Vec<u16> = store.iter().map(|account| account.client()).collect();
+    clients.sort_unstable();
+    assert_eq!(clients, vec![3, u16::MAX]);
+
+    store.remove(3);
+    store.remove(4);
+    assert_eq!(store.len(), 1);
+    assert!(store.get(3).is_none());
+    assert!(store.persist().is_ok());
+
+    store.clear();
+    assert!(store.is_empty());
+    assert_eq!(store.iter().count(), 0);
+}
+
+/// The map account store behaves as a map
+#[test]
+fn memory_account_store() {
+    exercise_accounts(MemoryAccountStore::default());
+}
+
+/// The dense account store behaves the same as the map store
+#[test]
+fn dense_account_store() {
+    exercise_accounts(DenseAccountStore::default());
+}
+
+/// A bank with a dense account store processes the same as the default
+#[test]
+fn dense_account_store_bank() {
+    let mut memory = Bank::new();
+    let mut dense = Bank::with_stores(
+        DenseAccountStore::default(),
+        MemoryTransactionStore::default(),
+        Policy::default(),
+    );
+    for file in ["locked.csv", "rejects.csv", "sorting.csv"] {
+        assert!(memory.process_transactions(test_file!(file)).is_ok());
+        assert!(dense.process_transactions(test_file!(file)).is_ok());
+    }
+    assert_eq!(dense.to_string(), memory.to_string());
+    assert_eq!(dense.num_accounts(), memory.num_accounts());
+}
@@ -0,0 +1,218 @@
+use super::amount;
+use crate::bank::Bank;
+use crate::error::EngineError;
+use crate::policy::Policy;
+use crate::transaction::Transaction;
+use crate::wal::Wal;
+use std::fs;
+use std::path::PathBuf;
+
+/// Returns a temporary path unique to this process.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("payengine_{}_{}", std::process::id(), name))
+}
+
+/// Replaying the log rebuilds the exact state, including accounts
+/// opened by rejected transactions and the rejections in histories
+#[test]
+fn wal_replay() {
+    let path = temp_path("replay.wal");
+
+    let mut bank = Bank::new();
+    bank.keep_history();
+    bank.set_wal(Wal::open(&path, 1).unwrap());
+    assert!(bank.process_transactions(test_file!("locked.csv")).is_ok());
+    assert!(bank.process_transactions(test_file!("rejects.csv")).is_ok());
+
+    let mut replayed = Bank::new();
+    replayed.keep_history();
+    assert!(replayed.replay_wal(&path).is_ok());
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed.to_string(), bank.to_string());
+    assert_eq!(replayed.num_logs(), bank.num_logs());
+    assert_eq!(
+        replayed.get_logged_transaction(1).unwrap().state,
+        bank.get_logged_transaction(1).unwrap().state
+    );
+    for client in 1..=3 {
+        assert_eq!(replayed.history(client), bank.history(client));
+    }
+}
+
+/// Saving a snapshot truncates the log, and the snapshot plus the
+/// log written after it rebuild the state
+#[test]
+fn wal_snapshot() {
+    let (path, snapshot) = (temp_path("snapshot.wal"), temp_path("wal_snapshot.json"));
+
+    let mut bank = Bank::new();
+    bank.set_wal(Wal::open(&path, 0).unwrap());
+    assert!(bank.process_transactions(test_file!("sorting.csv")).is_ok());
+    assert!(bank.save_snapshot(&snapshot).is_ok());
+    assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+    assert!(bank
+        .handle_transaction(Transaction::new("dispute", 5, 1, None))
+        .is_ok());
+
+    let mut restored = Bank::new();
+    assert!(restored.load_snapshot(&snapshot).is_ok());
+    assert_eq!(restored.replay_wal(&path).unwrap(), 1);
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&snapshot).unwrap();
+
+    assert_eq!(restored.to_string(), bank.to_string());
+}
+
+/// Expired holds are logged, so replaying the log expires them again
+#[test]
+fn wal_expiry() {
+    let path = temp_path("expiry.wal");
+
+    let mut bank = Bank::new();
+    bank.set_wal(Wal::open(&path, 1).unwrap());
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 1, Some(amount("2.0"))))
+        .is_ok());
+    assert!(bank
+        .handle_transaction(Transaction::authorize(1, 2, amount("1.5"), Some(100)))
+        .is_ok());
+    assert_eq!(bank.expire_holds(100), Ok(1));
+
+    let mut replayed = Bank::new();
+    assert_eq!(replayed.replay_wal(&path).unwrap(), 3);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed.get_account(1).unwrap().available, amount("2.0"));
+    assert_eq!(replayed.expire_holds(u64::MAX), Ok(0));
+    assert_eq!(
+        replayed.get_logged_transaction(2).unwrap().hold,
+        bank.get_logged_transaction(2).unwrap().hold
+    );
+}
+
+/// A capture of a hold that has run out logs the expiry before it, so
+/// the capture is refused again on replay
+#[test]
+fn wal_capture_expired() {
+    let path = temp_path("capture_expired.wal");
+
+    let mut bank = Bank::new();
+    bank.keep_history();
+    bank.set_wal(Wal::open(&path, 1).unwrap());
+    for transaction in [
+        Transaction::new("deposit", 1, 1, Some(amount("3.0"))),
+        Transaction::authorize(1, 2, amount("1.0"), Some(100)),
+        Transaction::authorize(1, 3, amount("1.0"), Some(200)),
+        Transaction::new("capture", 1, 3, None).at(150),
+    ] {
+        assert!(bank.handle_transaction(transaction).is_ok());
+    }
+    assert_eq!(
+        bank.handle_transaction(Transaction::new("capture", 1, 2, None)),
+        Err(EngineError::HoldNotActive { tx: 2 })
+    );
+
+    let mut replayed = Bank::new();
+    replayed.keep_history();
+    assert_eq!(replayed.replay_wal(&path).unwrap(), 6);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed.to_string(), bank.to_string());
+    for tx in [2, 3] {
+        assert_eq!(
+            replayed.get_logged_transaction(tx).unwrap().hold,
+            bank.get_logged_transaction(tx).unwrap().hold
+        );
+    }
+    assert_eq!(replayed.history(1), bank.history(1));
+}
+
+/// Replaying the log checks the order again, so the latest timestamp and
+/// the flags are rebuilt and undated disputes are checked against the
+/// same window
+#[test]
+fn wal_dispute_window() {
+    let path = temp_path("dispute_window.wal");
+    let policy = Policy {
+        dispute_window: Some(1000),
+        ..Default::default()
+    };
+
+    let mut bank = Bank::with_policy(policy.clone());
+    bank.set_wal(Wal::open(&path, 1).unwrap());
+    assert!(bank
+        .process_transactions(test_file!("dispute_window.csv"))
+        .is_ok());
+
+    let mut replayed = Bank::with_policy(policy);
+    assert_eq!(replayed.replay_wal(&path).unwrap(), 10);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed.to_string(), bank.to_string());
+    assert_eq!(replayed.latest_timestamp(), Some(11000));
+    assert_eq!(replayed.out_of_order(), bank.out_of_order());
+}
+
+/// A torn entry at the end of the log is skipped, and new entries
+/// start on a fresh line
+#[test]
+fn wal_torn_entry() {
+    let path = temp_path("torn.wal");
+    fs::write(
+        &path,
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.0\"}\n{\"type\":\"dep",
+    )
+    .unwrap();
+
+    let mut bank = Bank::new();
+    assert_eq!(bank.replay_wal(&path).unwrap(), 1);
+    bank.set_wal(Wal::open(&path, 1).unwrap());
+    assert!(bank
+        .handle_transaction(Transaction::new("withdrawal", 1, 2, Some(amount("0.5"))))
+        .is_ok());
+
+    let mut replayed = Bank::new();
+    assert_eq!(replayed.replay_wal(&path).unwrap(), 2);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed.get_account(1).unwrap().available, amount("1.5"));
+}
+
+/// A transaction that can't be logged is not applied
+#[cfg(target_os = "linux")]
+#[test]
+fn wal_storage_error() {
+    let mut bank = Bank::new();
+    assert!(bank
+        .handle_transaction(Transaction::new("deposit", 1, 1, Some(amount("1.0"))))
+        .is_ok());
+
+    // Writes to /dev/full always fail.
+    bank.set_wal(Wal::open("/dev/full", 1).unwrap());
+    assert!(matches!(
+        bank.handle_transaction(Transaction::new("withdrawal", 1, 2, Some(amount("0.5")))),
+        Err(EngineError::Storage { tx: 2, .. })
+    ));
+    assert!(matches!(
+        bank.handle_transaction(Transaction::new("deposit", 2, 3, Some(amount("0.5")))),
+        Err(EngineError::Storage { tx: 3, .. })
+    ));
+    assert!(matches!(
+        bank.handle_transaction(Transaction::transfer(1, 2, 4, amount("0.5"))),
+        Err(EngineError::Storage { tx: 4, .. })
+    ));
+    assert!(matches!(
+        bank.handle_transaction(Transaction::admin("freeze", 1, 5, "alice", Some("fraud"))),
+        Err(EngineError::Storage { tx: 5, .. })
+    ));
+
+    assert_eq!(bank.get_account(1).unwrap().available, amount("1.0"));
+    assert!(!bank.get_account(1).unwrap().is_locked());
+    assert!(bank.audit_log().is_empty());
+    assert!(bank.get_account(2).is_none());
+    assert!(bank.get_logged_transaction(2).is_none());
+    assert!(bank.get_logged_transaction(3).is_none());
+    assert!(bank.get_logged_transaction(4).is_none());
+}
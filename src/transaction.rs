@@ -1,15 +1,36 @@
-use anyhow::Context;
+use crate::amount::Amount;
+use crate::error::EngineError;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// A transaction contains a type, client, tx ID, and
 /// amount which could possibly not exist and will
 /// default to None. This allows for a small variety
 /// of formats to be accepted for deserialization:
-/// ```
+/// ```text
 /// deposit, 1, 1, 1.0
 /// DePosit, 1, 1,
 /// DEPOSIT, 1, 1
 /// ```
+/// Transfers also name a destination client, and
+/// authorizations can name when they expire as unix
+/// seconds, which the other types leave out:
+/// ```text
+/// transfer, 1, 2, 1.0, 3
+/// authorize, 1, 3, 1.0, , 1700000000
+/// ```
+/// Admin operations on an account name who made them,
+/// and why:
+/// ```text
+/// freeze, 1, 4, , , , alice, suspected fraud
+/// ```
+/// Any transaction can say when it happened as unix
+/// seconds, in a timestamp column named by the header:
+/// ```text
+/// type, client, tx, amount, timestamp
+/// deposit, 1, 5, 1.0, 1700000000
+/// ```
 /// Transaction aims to be accepting of a variety
 /// wide enough to allow for some runtime checks,
 /// such as types being in any capitalization, and
@@ -18,18 +39,87 @@ use serde::{Deserialize, Serialize};
 /// Amounts should be checked for existence
 /// when necessary as there is no sanity checks
 /// here for circumstances like if a deposit does
-/// not have an amount, or a dispute does have one.
-#[derive(Serialize, Deserialize, Debug)]
+/// not have an amount. Disputes, resolves and
+/// chargebacks may have one to act on part of
+/// the transaction they reference.
+/// Amounts with more than four decimal places fail
+/// to deserialize rather than being rounded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Transaction {
     r#type: String,
     pub client: u16,
     pub tx: u32,
     // Handle missing field
     #[serde(default)]
-    pub amount: Option<f32>,
+    pub amount: Option<Amount>,
+    // Only transfers have a destination
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<u16>,
+    // Only authorizations expire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    // Only admin operations have an actor and reason
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    // When the transaction happened, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
 }
 
 impl Transaction {
+    /// Create a new transaction of a type for a client.
+    pub fn new(r#type: &str, client: u16, tx: u32, amount: Option<Amount>) -> Self {
+        Self {
+            r#type: r#type.to_string(),
+            client,
+            tx,
+            amount,
+            destination: None,
+            expires: None,
+            actor: None,
+            reason: None,
+            timestamp: None,
+        }
+    }
+
+    /// Create a new transfer of an amount from a client to a
+    /// destination client.
+    pub fn transfer(client: u16, destination: u16, tx: u32, amount: Amount) -> Self {
+        Self {
+            destination: Some(destination),
+            ..Self::new("transfer", client, tx, Some(amount))
+        }
+    }
+
+    /// Create a new authorization of an amount for a client, which
+    /// expires at a time in unix seconds if one is given.
+    pub fn authorize(client: u16, tx: u32, amount: Amount, expires: Option<u64>) -> Self {
+        Self {
+            expires,
+            ..Self::new("authorize", client, tx, Some(amount))
+        }
+    }
+
+    /// Create a new admin operation of a type on a client's account,
+    /// made by an actor for a reason if one is given.
+    pub fn admin(r#type: &str, client: u16, tx: u32, actor: &str, reason: Option<&str>) -> Self {
+        Self {
+            actor: Some(actor.to_string()),
+            reason: reason.map(str::to_string),
+            ..Self::new(r#type, client, tx, None)
+        }
+    }
+
+    /// Sets when the transaction happened in unix seconds.
+    pub fn at(self, timestamp: u64) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
     /// Returns the type as a lowercase string.
     pub fn get_type(&self) -> String {
         self.r#type.to_lowercase()
@@ -45,16 +135,108 @@ impl Transaction {
 /// Note that this type is lossy and can't be
 /// transformed back into a Transaction without
 /// recovering the lost data from elsewhere.
-#[derive(Debug)]
+///
+/// Transfers are logged with their destination, so
+/// that the funds can be found again on a dispute.
+/// Authorizations are logged with their Hold.
+///
+/// The portions of the amount currently disputed,
+/// resolved and charged back are tracked separately,
+/// as disputes can be for part of a transaction.
+///
+/// The timestamp is kept so that disputes can be
+/// checked against the dispute window.
+#[derive(Clone, Debug)]
 pub struct LoggedTransaction {
     pub client: u16,
-    pub amount: f32,
+    pub amount: Amount,
     pub deposit: bool,
-    pub disputed: bool,
+    pub destination: Option<u16>,
+    pub hold: Option<Hold>,
+    pub state: DisputeState,
+    pub disputed: Amount,
+    /// The portion that was disputed and resolved, and isn't disputed
+    /// again since.
+    pub resolved: Amount,
+    pub charged_back: Amount,
+    pub timestamp: Option<u64>,
+}
+
+impl LoggedTransaction {
+    /// Returns the portion of the amount that is neither disputed
+    /// nor charged back.
+    pub fn undisputed(&self) -> Amount {
+        self.amount
+            .checked_sub(self.disputed)
+            .and_then(|rest| rest.checked_sub(self.charged_back))
+            .unwrap_or_default()
+    }
+
+    /// Returns the portion of the amount that was never disputed, which
+    /// is what is left undisputed besides the resolved portion.
+    pub fn never_disputed(&self) -> Amount {
+        self.undisputed()
+            .checked_sub(self.resolved)
+            .unwrap_or_default()
+    }
+}
+
+/// A Hold is the state of a logged authorization, and when it
+/// expires in unix seconds if it does.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hold {
+    pub state: HoldState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+/// Returns the current time in unix seconds, as hold expiries are
+/// given in. A clock set before 1970 reads as 0.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The HoldState of a logged authorization. Authorizations start
+/// Authorized, and end in one of the other states:
+/// ```text
+/// Authorized -> Captured
+///            -> Released
+///            -> Expired
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldState {
+    Authorized,
+    Captured,
+    Released,
+    Expired,
+}
+
+/// The DisputeState of a logged transaction. Transactions start
+/// Undisputed, and move between states as follows:
+/// ```text
+/// Undisputed -> Disputed -> Resolved
+///                        -> ChargedBack
+/// Disputed   -> Disputed (while part of it is still disputed)
+/// Resolved   -> Disputed (if the policy allows re-disputes)
+/// ```
+/// A transaction is Resolved or ChargedBack once none of it is
+/// disputed anymore. ChargedBack is terminal, no further disputes
+/// are possible.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    #[default]
+    Undisputed,
+    Disputed,
+    Resolved,
+    ChargedBack,
 }
 
 impl TryFrom<Transaction> for LoggedTransaction {
-    type Error = anyhow::Error;
+    type Error = EngineError;
 
     /// Converts a Transaction into a LoggedTransaction,
     /// dropping unnecessary data for logging.
@@ -63,9 +245,43 @@ impl TryFrom<Transaction> for LoggedTransaction {
             client: value.client,
             amount: value
                 .amount
-                .context(format!("Transaction {} has no amount", value.tx))?,
+                .ok_or(EngineError::MissingAmount { tx: value.tx })?,
             deposit: value.get_type() == "deposit",
-            disputed: false,
+            destination: value.destination,
+            hold: (value.get_type() == "authorize").then_some(Hold {
+                state: HoldState::Authorized,
+                expires: value.expires,
+            }),
+            state: DisputeState::Undisputed,
+            disputed: Amount::default(),
+            resolved: Amount::default(),
+            charged_back: Amount::default(),
+            timestamp: value.timestamp,
         })
     }
 }
+
+/// An OutOfOrder flags a transaction whose timestamp is earlier than
+/// the latest one already seen, as inputs are expected to be in the
+/// order things happened:
+/// ```text
+/// {"tx":5,"client":1,"timestamp":1700000000,"latest":1700000060}
+/// ```
+/// Flagged transactions are still handled.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OutOfOrder {
+    pub tx: u32,
+    pub client: u16,
+    pub timestamp: u64,
+    pub latest: u64,
+}
+
+impl Display for OutOfOrder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Transaction {} at {} is out of order, after {}",
+            self.tx, self.timestamp, self.latest
+        )
+    }
+}
@@ -0,0 +1,101 @@
+use crate::transaction::Transaction;
+use anyhow::Result;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The number of entries written between syncs by default.
+pub const DEFAULT_SYNC_EVERY: usize = 1000;
+
+/// A Wal is an append-only write-ahead log of the transactions handled
+/// by a Bank, written before each is applied, one NDJSON transaction
+/// per line:
+/// ```text
+/// {"type":"deposit","client":1,"tx":1,"amount":"1.0000"}
+/// {"type":"dispute","client":1,"tx":1,"amount":null}
+/// ```
+/// Every entry is written to the file as soon as it is appended, so
+/// it survives the process crashing. Entries are only synced to disk
+/// every `sync_every` appends, trading the last few entries on a
+/// machine crash for not syncing on every transaction. A `sync_every`
+/// of 0 only syncs when `sync` is called.
+///
+/// Replaying the entries on top of the snapshot the log was started
+/// from, or an empty state, rebuilds the exact state, as each entry is
+/// accepted or rejected again just as it was the first time. See
+/// `Bank::replay_wal`.
+pub struct Wal {
+    file: File,
+    sync_every: usize,
+    unsynced: usize,
+}
+
+impl Wal {
+    /// Opens a Wal at a path for appending, creating it if it does
+    /// not exist, and syncing every `sync_every` entries.
+    ///
+    /// Returns an Err if the file can't be opened.
+    pub fn open<P: AsRef<Path>>(path: P, sync_every: usize) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        // A crash can leave a torn entry at the end of the log, which
+        // is skipped on replay. Start a fresh line so the next entry
+        // isn't glued onto it.
+        if file.metadata()?.len() > 0 {
+            let mut last = [0; 1];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+
+        Ok(Self {
+            file,
+            sync_every,
+            unsynced: 0,
+        })
+    }
+
+    /// Appends a transaction to the log, syncing if enough entries
+    /// have been appended since the last sync.
+    ///
+    /// Returns an Err if the entry can't be written.
+    pub fn append(&mut self, transaction: &Transaction) -> Result<()> {
+        let mut entry = serde_json::to_vec(transaction)?;
+        entry.push(b'\n');
+        // Write the entry in one go so a crash can only tear the last line.
+        self.file.write_all(&entry)?;
+
+        self.unsynced += 1;
+        if self.sync_every > 0 && self.unsynced >= self.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs every appended entry to disk.
+    ///
+    /// Returns an Err if the file can't be synced.
+    pub fn sync(&mut self) -> Result<()> {
+        if self.unsynced > 0 {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+
+    /// Empties the log, once its entries are covered by a snapshot.
+    ///
+    /// Returns an Err if the file can't be truncated.
+    pub fn truncate(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}